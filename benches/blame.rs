@@ -0,0 +1,113 @@
+//! Times the full blame computation on a synthetic lock file with 5,000 packages and heavy fan-in
+//! (similar to `syn`, `proc-macro2` and `serde` in a large workspace). Run with `cargo bench`.
+
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::Instant;
+
+use cargo_depcheck::{
+    BlameMode, Config, Deps, MultiVerDepParents, MultiVerDepResults, MultiVerDeps,
+};
+use cargo_lock::Lockfile;
+
+const PACKAGES: usize = 5_000;
+const LAYERS: usize = 50;
+const DEPS_PER_PACKAGE: usize = 6;
+const MULTI_VER_PACKAGES: usize = 40;
+const ITERATIONS: u32 = 5;
+
+/// Deterministic pseudo random numbers so every run benchmarks the same lock file
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, max: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) as usize) % max
+    }
+}
+
+/// Builds a layered DAG: each package depends on packages from lower layers only. The bottom layer
+/// consists of packages with two versions each, which every package above can reach in many ways.
+fn synthetic_lock_file() -> String {
+    let mut rng = Lcg(42);
+    let per_layer = PACKAGES / LAYERS;
+    let mut lock = String::from("version = 4\n");
+
+    for idx in 0..MULTI_VER_PACKAGES {
+        for major in 1..=2 {
+            writeln!(
+                lock,
+                "\n[[package]]\nname = \"base{idx}\"\nversion = \"{major}.0.0\"\nsource = \"registry+https://github.com/rust-lang/crates.io-index\""
+            )
+            .unwrap();
+        }
+    }
+
+    for layer in 0..LAYERS {
+        for idx in 0..per_layer {
+            let top_level = layer == LAYERS - 1;
+            let mut deps = Vec::with_capacity(DEPS_PER_PACKAGE);
+
+            for _ in 0..DEPS_PER_PACKAGE {
+                let dep = if layer == 0 || rng.next(4) == 0 {
+                    format!(
+                        "base{} {}.0.0",
+                        rng.next(MULTI_VER_PACKAGES),
+                        rng.next(2) + 1
+                    )
+                } else {
+                    format!("pkg{}_{}", rng.next(layer), rng.next(per_layer))
+                };
+
+                if !deps.contains(&dep) {
+                    deps.push(dep);
+                }
+            }
+            deps.sort();
+
+            write!(
+                lock,
+                "\n[[package]]\nname = \"pkg{layer}_{idx}\"\nversion = \"1.0.0\"\n"
+            )
+            .unwrap();
+            if !top_level {
+                lock.push_str(
+                    "source = \"registry+https://github.com/rust-lang/crates.io-index\"\n",
+                );
+            }
+            lock.push_str("dependencies = [\n");
+            for dep in deps {
+                writeln!(lock, " \"{dep}\",").unwrap();
+            }
+            lock.push_str("]\n");
+        }
+    }
+
+    lock
+}
+
+fn main() {
+    let lock = synthetic_lock_file();
+    let config = Config::default();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let lock_file = Lockfile::from_str(&lock).unwrap();
+        let deps = Deps::from_lock_file(lock_file).unwrap();
+        let multi_ver_deps = MultiVerDeps::from_deps(&deps, &config).unwrap();
+        let parents = MultiVerDepParents::build(&deps, &multi_ver_deps).unwrap();
+        let results =
+            MultiVerDepResults::build(&deps, &parents, multi_ver_deps, None, Some(BlameMode::All))
+                .unwrap();
+        assert!(results.return_error(Some(BlameMode::All)));
+    }
+
+    println!(
+        "blame ({} packages): {:?} per iteration",
+        PACKAGES + MULTI_VER_PACKAGES * 2,
+        start.elapsed() / ITERATIONS
+    );
+}
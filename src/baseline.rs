@@ -0,0 +1,221 @@
+//! A baseline records the multi version dependencies and direct blame of a previous run so that later
+//! runs only fail on newly introduced duplicates (a "ratchet").
+
+use std::path::Path;
+
+use crate::multi_ver_deps::MultiVerDeps;
+use crate::{DIRECT, Package};
+
+use cargo_lock::Name;
+use indexmap::{IndexMap, IndexSet};
+use serde::{Deserialize, Serialize, Serializer};
+
+const BASELINE_FORMAT_VERSION: u32 = 1;
+
+// *** BaselineBlame ***
+
+/// A package (at a single version and source) with direct blame
+#[derive(Deserialize, Serialize)]
+struct BaselineBlame {
+    package: Package,
+
+    /// Multi version dependency names the package is directly to blame for
+    duplicates: IndexSet<Name>,
+}
+
+// *** Baseline ***
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct Baseline {
+    format_version: u32,
+
+    /// Every version (and source) of each multi version dependency
+    duplicates: IndexSet<Package>,
+
+    /// Packages with direct blame, sorted by package
+    direct_blame: Vec<BaselineBlame>,
+}
+
+impl Baseline {
+    pub(crate) fn build<'a>(
+        multi_ver_deps: &MultiVerDeps,
+        direct_blame: impl Iterator<Item = (&'a Package, &'a Name)>,
+    ) -> Self {
+        let mut blame: IndexMap<_, IndexSet<_>> = IndexMap::new();
+        for (package, name) in direct_blame {
+            blame
+                .entry(package.clone())
+                .or_default()
+                .insert(name.clone());
+        }
+        blame.values_mut().for_each(|names| names.sort());
+        blame.sort_unstable_keys();
+
+        let mut duplicates: IndexSet<_> = multi_ver_deps
+            .iter()
+            .flat_map(|(_, mv_dep)| mv_dep.packages().cloned())
+            .collect();
+        duplicates.sort_unstable();
+
+        Self {
+            format_version: BASELINE_FORMAT_VERSION,
+            duplicates,
+            direct_blame: blame
+                .into_iter()
+                .map(|(package, duplicates)| BaselineBlame {
+                    package,
+                    duplicates,
+                })
+                .collect(),
+        }
+    }
+
+    /// Package -> multi version dependency names it is directly to blame for
+    fn direct_blame_map(&self) -> IndexMap<&Package, &IndexSet<Name>> {
+        self.direct_blame
+            .iter()
+            .map(|blame| (&blame.package, &blame.duplicates))
+            .collect()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read baseline '{}': {e}", path.display()))?;
+        let baseline: Self = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid baseline '{}': {e}", path.display()))?;
+
+        if baseline.format_version != BASELINE_FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported baseline format version {} in '{}' (record it again with --write-baseline)",
+                baseline.format_version,
+                path.display()
+            ));
+        }
+
+        Ok(baseline)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, contents + "\n")
+            .map_err(|e| format!("Unable to write baseline '{}': {e}", path.display()))
+    }
+}
+
+// *** BaselineRegressions ***
+
+fn serialize_versions<S: Serializer>(
+    versions: &IndexMap<Name, IndexSet<Package>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(versions.iter().map(|(name, pkgs)| {
+        (
+            name,
+            pkgs.iter()
+                .map(Package::display_version)
+                .collect::<Vec<_>>(),
+        )
+    }))
+}
+
+fn serialize_blame<S: Serializer>(
+    blame: &IndexMap<Package, IndexSet<Name>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(
+        blame
+            .iter()
+            .map(|(package, names)| (package.to_string(), names)),
+    )
+}
+
+/// Everything in the current run that was not present in the baseline
+#[derive(Default, Serialize)]
+pub(crate) struct BaselineRegressions {
+    /// Multi version dependencies that did not have multiple versions in the baseline
+    new_duplicates: IndexSet<Name>,
+
+    /// New versions (or sources) of multi version dependencies that were already present in the
+    /// baseline
+    #[serde(serialize_with = "serialize_versions")]
+    new_versions: IndexMap<Name, IndexSet<Package>>,
+
+    /// New direct blame (package version -> multi version dependency names)
+    #[serde(serialize_with = "serialize_blame")]
+    new_direct_blame: IndexMap<Package, IndexSet<Name>>,
+}
+
+impl BaselineRegressions {
+    pub fn build(baseline: &Baseline, current: &Baseline) -> Self {
+        let mut regressions = Self::default();
+        let base_names: IndexSet<_> = baseline.duplicates.iter().map(|pkg| &pkg.name).collect();
+
+        for pkg in &current.duplicates {
+            if !base_names.contains(&pkg.name) {
+                regressions.new_duplicates.insert(pkg.name.clone());
+            } else if !baseline.duplicates.contains(pkg) {
+                regressions
+                    .new_versions
+                    .entry(pkg.name.clone())
+                    .or_default()
+                    .insert(pkg.clone());
+            }
+        }
+
+        // A newly blamed version of a package is new blame, even if another version was blamed before
+        let base_blame = baseline.direct_blame_map();
+        for blame in &current.direct_blame {
+            let new_names: IndexSet<_> = match base_blame.get(&blame.package) {
+                Some(base_names) => blame.duplicates.difference(base_names).cloned().collect(),
+                None => blame.duplicates.clone(),
+            };
+
+            if !new_names.is_empty() {
+                regressions
+                    .new_direct_blame
+                    .insert(blame.package.clone(), new_names);
+            }
+        }
+
+        regressions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.new_duplicates.is_empty()
+            && self.new_versions.is_empty()
+            && self.new_direct_blame.is_empty()
+    }
+
+    fn join<T: std::fmt::Display>(items: &IndexSet<T>) -> String {
+        items
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        for name in &self.new_duplicates {
+            writeln!(w, "{DIRECT}New duplicate: {name}{DIRECT:#}")?;
+        }
+
+        for (name, versions) in &self.new_versions {
+            let versions = versions
+                .iter()
+                .map(Package::display_version)
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(w, "{DIRECT}New version(s) of {name}: {versions}{DIRECT:#}")?;
+        }
+
+        for (package, names) in &self.new_direct_blame {
+            writeln!(
+                w,
+                "{DIRECT}New direct blame for {package}: {}{DIRECT:#}",
+                Self::join(names)
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -2,93 +2,360 @@
 //!
 //! The structure tree looks like this: Parent -> Dup Dep Name -> Dup Dep Version -> Parent Dependency
 
-use crate::{MultiVerDepParents, Package, DIRECT, INDIRECT, NO_DUP};
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::dep_tree::{Deps, PkgId};
+use crate::graph::BitSet;
+use crate::{DIRECT, INDIRECT, MultiVerDepParents, NO_DUP, Package};
 
 use anstyle::{AnsiColor, Style};
-use cargo_lock::{Name, Version};
+use cargo_lock::{Name, SourceId, Version};
 use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+use serde::ser::{SerializeSeq, SerializeStruct};
 
 const TL_DEP: Style = AnsiColor::Blue.on_default();
 
 // *** MultiVerDepBlameDep ***
 
-/// The dependencies directly specified by the top level parent package
-#[derive(Default)]
-pub(crate) struct MultiVerDepBlameDep(IndexSet<Package>);
+/// The dependencies directly specified by the top level parent package, and the packages at or below
+/// them that directly depend on the multi version dependency version
+#[derive(Clone, Default)]
+pub(crate) struct MultiVerDepBlameDep {
+    deps: IndexSet<Package>,
+
+    /// Direct dependents of the version -> the version requirement they declare (if known)
+    requirements: IndexMap<Package, Option<String>>,
+}
 
 impl MultiVerDepBlameDep {
     pub fn insert(&mut self, package: Package) {
-        self.0.insert(package);
+        self.deps.insert(package);
+    }
+
+    pub fn insert_dependent(&mut self, package: Package) {
+        self.requirements.insert(package, None);
     }
 
     pub fn sort(&mut self) {
-        self.0.sort_unstable();
+        self.deps.sort_unstable();
+        self.requirements.sort_unstable_keys();
     }
 
     pub fn render<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
         write!(w, "{TL_DEP}--> ")?;
 
-        for (idx, pkg) in self.0.iter().enumerate() {
+        for (idx, pkg) in self.deps.iter().enumerate() {
             write!(w, "{pkg}")?;
-            if idx < self.0.len() - 1 {
+            if idx < self.deps.len() - 1 {
                 write!(w, ", ")?;
             }
         }
 
         writeln!(w, "{TL_DEP:#}")
     }
+
+    pub fn render_requirements<W: std::fmt::Write>(
+        &self,
+        w: &mut W,
+        name: &Name,
+    ) -> std::fmt::Result {
+        for (dependent, requirement) in &self.requirements {
+            match requirement {
+                Some(requirement) => writeln!(w, "        {dependent} requires {requirement}")?,
+                None => writeln!(
+                    w,
+                    "        {dependent} requires {name} (requirement unknown)"
+                )?,
+            }
+        }
+
+        Ok(())
+    }
 }
 
 // *** MultiVerDepBlameVer ***
 
 /// Tracks duplicate dependency version and it's mapping to the top level parent dependency
-pub(crate) struct MultiVerDepBlameVer(IndexMap<Version, MultiVerDepBlameDep>);
+#[derive(Clone)]
+pub(crate) struct MultiVerDepBlameVer(IndexMap<Package, MultiVerDepBlameDep>);
 
 impl MultiVerDepBlameVer {
     pub fn build(
-        name: &Name,
-        versions: &IndexSet<Version>,
-        parent_deps: &IndexSet<Package>,
+        parent: PkgId,
+        bits: Range<usize>,
+        versions: &BitSet,
         parents: &MultiVerDepParents,
+        deps: &Deps,
     ) -> Self {
-        let mut ver_entries = Self(IndexMap::new());
+        let versions: Vec<_> = versions.iter_range(bits.clone()).collect();
+
+        // Bit of each version -> the parent's dependencies that bring it in
+        let mut parent_deps_of: IndexMap<usize, Vec<PkgId>> = IndexMap::new();
 
         // Process all the dependencies of the parent package...
-        for parent_dep in parent_deps {
-            // ... but we only process dependencies that have our same multi version dependency
-            if let Some(dep_mv_deps) = parents.get_multi_ver_deps(parent_dep) {
-                // Only if dependency has some versions of it do we do anything at all
-                if let Some(dep_versions) = dep_mv_deps.get(name) {
-                    // If dependency versions aren't equal to parent's then there must be
-                    // at least one other dependency that has a different version therefore
-                    // we are directly to blame
-                    if dep_versions != versions {
-                        // Keep track of all versions of the dependency used by this package
-                        for dep_ver in dep_versions {
-                            ver_entries
-                                .0
-                                .entry(dep_ver.clone())
-                                .or_default()
-                                .insert(parent_dep.clone());
-                        }
-                    }
+        for &parent_dep in deps.node(parent).dependencies() {
+            // ... but we only process dependencies that bring in our same multi version dependency
+            // (including when the dependency is one of its versions)
+            let dep_versions = parents.carried(parent_dep, bits.clone());
+
+            // If dependency versions aren't equal to parent's then there must be at least one other
+            // dependency that has a different version therefore we are directly to blame
+            if !dep_versions.is_empty() && dep_versions != versions {
+                // Keep track of all versions of the dependency used by this package
+                for bit in dep_versions {
+                    parent_deps_of.entry(bit).or_default().push(parent_dep);
                 }
             }
         }
 
-        ver_entries.0.values_mut().for_each(|pkg| pkg.sort());
+        let mut ver_entries = Self(IndexMap::with_capacity(parent_deps_of.len()));
+        for (bit, parent_deps) in parent_deps_of {
+            let mut entry = MultiVerDepBlameDep::default();
+            for &parent_dep in &parent_deps {
+                entry.insert(deps.package(parent_dep).clone());
+            }
+            for dependent in Self::direct_dependents(parent, bit, &parent_deps, parents, deps) {
+                entry.insert_dependent(deps.package(dependent).clone());
+            }
+
+            entry.sort();
+            ver_entries
+                .0
+                .insert(deps.package(parents.bit_id(bit)).clone(), entry);
+        }
+
         ver_entries.0.sort_unstable_keys();
         ver_entries
     }
 
+    /// Finds the packages that directly depend on the version of the bit, starting at the parent's
+    /// dependencies and only walking down through packages that (directly or indirectly) depend on it
+    fn direct_dependents(
+        parent: PkgId,
+        bit: usize,
+        parent_deps: &[PkgId],
+        parents: &MultiVerDepParents,
+        deps: &Deps,
+    ) -> IndexSet<PkgId> {
+        let version = parents.bit_id(bit);
+        let mut dependents = IndexSet::new();
+        let mut visited = HashSet::new();
+        let mut work_queue = Vec::with_capacity(parent_deps.len());
+
+        for &parent_dep in parent_deps {
+            if parent_dep == version {
+                dependents.insert(parent);
+            } else {
+                work_queue.push(parent_dep);
+            }
+        }
+
+        while let Some(id) = work_queue.pop() {
+            // Dependencies can form cycles, so never walk the same package twice
+            if !visited.insert(id) {
+                continue;
+            }
+
+            for &dep_id in deps.node(id).dependencies() {
+                if dep_id == version {
+                    dependents.insert(id);
+                } else if parents
+                    .get_multi_ver_deps(dep_id)
+                    .is_some_and(|dep_versions| dep_versions.contains(bit))
+                {
+                    work_queue.push(dep_id);
+                }
+            }
+        }
+
+        dependents
+    }
+
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
 
+    /// Only keeps the versions matching the predicate
+    pub fn retain_versions(&mut self, mut keep: impl FnMut(&Package) -> bool) {
+        self.0.retain(|pkg, _| keep(pkg));
+    }
+
+    /// The direct dependents of each version and their (still unknown) requirements
+    fn requirements_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (&Package, &Package, &mut Option<String>)> {
+        self.0.iter_mut().flat_map(|(pkg, entry)| {
+            entry
+                .requirements
+                .iter_mut()
+                .map(move |(dependent, requirement)| (dependent, pkg, requirement))
+        })
+    }
+
     pub fn render<W: std::fmt::Write>(&self, w: &mut W, name: &Name) -> std::fmt::Result {
-        for (version, deps) in &self.0 {
+        for (pkg, deps) in &self.0 {
             deps.render(w)?;
-            writeln!(w, "      {name} {version}")?;
+            writeln!(w, "      {name} {}", pkg.display_version())?;
+            deps.render_requirements(w, name)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Serialize for MultiVerDepBlameVer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Requirement<'a> {
+            dependent: &'a Package,
+            requirement: &'a Option<String>,
+        }
+
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            version: &'a Version,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            source: &'a Option<SourceId>,
+            dependencies: &'a IndexSet<Package>,
+            requirements: Vec<Requirement<'a>>,
+        }
+
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for (pkg, entry) in &self.0 {
+            seq.serialize_element(&Entry {
+                version: &pkg.version,
+                source: &pkg.source,
+                dependencies: &entry.deps,
+                requirements: entry
+                    .requirements
+                    .iter()
+                    .map(|(dependent, requirement)| Requirement {
+                        dependent,
+                        requirement,
+                    })
+                    .collect(),
+            })?;
+        }
+        seq.end()
+    }
+}
+
+// *** MultiVerDepBlameChain ***
+
+/// The dependencies that carry every version of a multi version dependency an indirectly blamed package
+/// depends on, and the chain through them down to a package that is directly to blame
+#[derive(Serialize)]
+pub(crate) struct MultiVerDepBlameChain {
+    /// Dependencies of the blamed package that each bring in all of the versions
+    carriers: IndexSet<Package>,
+
+    /// The blamed package, then one carrier at each level down to the package directly to blame
+    chain: Vec<Package>,
+
+    /// The versions carried
+    versions: Vec<Package>,
+}
+
+impl MultiVerDepBlameChain {
+    pub fn build(
+        parent: PkgId,
+        bits: Range<usize>,
+        parents: &MultiVerDepParents,
+        deps: &Deps,
+    ) -> Self {
+        let carriers = Self::carriers(parent, bits.clone(), parents, deps).unwrap_or_default();
+
+        // Follow the first carrier down until the versions are split across dependencies (or there is
+        // nothing left to follow). Cycles carry the same versions, so never revisit a package.
+        let mut chain = vec![parent];
+        let mut next = carriers.first().copied();
+        while let Some(id) = next {
+            chain.push(id);
+            next = Self::carriers(id, bits.clone(), parents, deps)
+                .and_then(|carriers| carriers.into_iter().find(|id| !chain.contains(id)));
+        }
+
+        let versions = match parents.get_multi_ver_deps(parent) {
+            Some(versions) => versions
+                .iter_range(bits)
+                .map(|bit| deps.package(parents.bit_id(bit)).clone())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Self {
+            carriers: carriers
+                .into_iter()
+                .map(|id| deps.package(id).clone())
+                .collect(),
+            chain: chain
+                .into_iter()
+                .map(|id| deps.package(id).clone())
+                .collect(),
+            versions,
+        }
+    }
+
+    /// The dependencies that each bring in every version the package depends on (sorted), or `None` if
+    /// the versions are split across its dependencies or it depends on one of them itself (it is
+    /// directly to blame)
+    fn carriers(
+        id: PkgId,
+        bits: Range<usize>,
+        parents: &MultiVerDepParents,
+        deps: &Deps,
+    ) -> Option<Vec<PkgId>> {
+        let versions: Vec<_> = parents
+            .get_multi_ver_deps(id)?
+            .iter_range(bits.clone())
+            .collect();
+        let mut carriers = Vec::new();
+
+        for &dep_id in deps.node(id).dependencies() {
+            let dep_versions = parents.carried(dep_id, bits.clone());
+            if !dep_versions.is_empty() {
+                if dep_versions != versions {
+                    return None;
+                }
+                carriers.push(dep_id);
+            }
+        }
+
+        carriers.sort_unstable_by_key(|&id| deps.package(id));
+        Some(carriers)
+    }
+
+    /// True if the version is one of those carried
+    pub fn carries(&self, version: &Package) -> bool {
+        self.versions.contains(version)
+    }
+
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W, name: &Name) -> std::fmt::Result {
+        write!(w, "    ")?;
+        for pkg in &self.chain {
+            write!(w, "{pkg} -> ")?;
+        }
+
+        let versions = self
+            .versions
+            .iter()
+            .map(Package::display_version)
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(w, "{name} {{{versions}}}")?;
+
+        // Only the first carrier is followed, so mention the others
+        if self.carriers.len() > 1 {
+            let others = self
+                .carriers
+                .iter()
+                .skip(1)
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(w, "      also carried by {others}")?;
         }
 
         Ok(())
@@ -103,34 +370,37 @@ pub(crate) struct MultiVerDepBlameEntry {
     direct: IndexMap<Name, MultiVerDepBlameVer>,
 
     /// Packages that have multiple versions this package is indirectly responsible for (by including
-    /// a package that itself has direct/indirect multi version responsibilities)
-    indirect: IndexSet<Name>,
+    /// a package that itself has direct/indirect multi version responsibilities), and the dependencies
+    /// that carry them
+    indirect: IndexMap<Name, MultiVerDepBlameChain>,
 }
 
 impl MultiVerDepBlameEntry {
-    pub fn build(
-        parent_pkg: &Package,
-        parent_deps: &IndexSet<Package>,
-        parents: &MultiVerDepParents,
-    ) -> Self {
+    pub fn build(parent_pkg: PkgId, parents: &MultiVerDepParents, deps: &Deps) -> Self {
         let mut entry = Self {
             direct: IndexMap::new(),
-            indirect: IndexSet::new(),
+            indirect: IndexMap::new(),
         };
 
         // We only assign blame if we are a dependent of a multi version dependency
         if let Some(multi_ver_deps) = parents.get_multi_ver_deps(parent_pkg) {
             // Handle each package where we are a dependent of the dependency
-            for (name, versions) in multi_ver_deps.multi_ver_iter() {
+            for (name, bits) in parents.names() {
                 // If true, we know we have multiple versions, so direct or indirect blame
                 // will be assigned beyond this point
-                if versions.len() > 1 {
-                    let direct_blame_deps =
-                        MultiVerDepBlameVer::build(name, versions, parent_deps, parents);
+                if multi_ver_deps.iter_range(bits.clone()).nth(1).is_some() {
+                    let direct_blame_deps = MultiVerDepBlameVer::build(
+                        parent_pkg,
+                        bits.clone(),
+                        multi_ver_deps,
+                        parents,
+                        deps,
+                    );
 
                     // If we have entries than we are to blame directly otherwise indirectly
                     if direct_blame_deps.is_empty() {
-                        entry.indirect.insert(name.clone());
+                        let chain = MultiVerDepBlameChain::build(parent_pkg, bits, parents, deps);
+                        entry.indirect.insert(name.clone(), chain);
                     } else {
                         entry.direct.insert(name.clone(), direct_blame_deps);
                     }
@@ -139,7 +409,7 @@ impl MultiVerDepBlameEntry {
         }
 
         entry.direct.sort_unstable_keys();
-        entry.indirect.sort_unstable();
+        entry.indirect.sort_unstable_keys();
         entry
     }
 
@@ -147,6 +417,15 @@ impl MultiVerDepBlameEntry {
         !self.direct.is_empty()
     }
 
+    /// The direct dependents behind each direct blame, the version they depend on, and its requirement
+    pub fn requirements_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (&Package, &Package, &mut Option<String>)> {
+        self.direct
+            .values_mut()
+            .flat_map(MultiVerDepBlameVer::requirements_mut)
+    }
+
     pub fn has_indirect_blame(&self) -> bool {
         !self.indirect.is_empty()
     }
@@ -174,10 +453,55 @@ impl MultiVerDepBlameEntry {
             }
         }
 
+        if blame_detail && self.has_indirect_blame() {
+            writeln!(w, "  Indirect:")?;
+            for (name, chain) in &self.indirect {
+                chain.render(w, name)?;
+            }
+        }
+
         Ok(())
     }
 }
 
+impl Serialize for MultiVerDepBlameEntry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Direct<'a> {
+            name: &'a Name,
+            versions: &'a MultiVerDepBlameVer,
+        }
+
+        #[derive(Serialize)]
+        struct Indirect<'a> {
+            name: &'a Name,
+            #[serde(flatten)]
+            chain: &'a MultiVerDepBlameChain,
+        }
+
+        // `indirect` stays a list of names, the chains are listed separately
+        let mut state = serializer.serialize_struct("MultiVerDepBlameEntry", 3)?;
+        state.serialize_field(
+            "direct",
+            &self
+                .direct
+                .iter()
+                .map(|(name, versions)| Direct { name, versions })
+                .collect::<Vec<_>>(),
+        )?;
+        state.serialize_field("indirect", &self.indirect.keys().collect::<Vec<_>>())?;
+        state.serialize_field(
+            "indirect_chains",
+            &self
+                .indirect
+                .iter()
+                .map(|(name, chain)| Indirect { name, chain })
+                .collect::<Vec<_>>(),
+        )?;
+        state.end()
+    }
+}
+
 // *** MultiVerDepBlame ***
 
 /// Top level representing the package mapping to the duplication dependencies
@@ -193,14 +517,52 @@ impl MultiVerDepBlame {
         self.0.sort_unstable_keys();
     }
 
-    pub fn contains(&self, package: &Package) -> bool {
-        self.0.contains_key(package)
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 
     pub fn has_direct_blame(&self) -> bool {
         self.0.values().any(|entry| entry.has_direct_blame())
     }
 
+    /// The direct dependents behind all direct blame, the version they depend on, and its requirement
+    pub fn requirements_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (&Package, &Package, &mut Option<String>)> {
+        self.0
+            .values_mut()
+            .flat_map(MultiVerDepBlameEntry::requirements_mut)
+    }
+
+    /// Packages directly to blame for the multi version dependency, and the versions they bring in
+    pub fn direct_for<'a>(
+        &'a self,
+        name: &Name,
+    ) -> impl Iterator<Item = (&'a Package, &'a MultiVerDepBlameVer)> + use<'a> {
+        let name = name.clone();
+        self.0
+            .iter()
+            .filter_map(move |(package, entry)| Some((package, entry.direct.get(&name)?)))
+    }
+
+    /// Packages indirectly to blame for the multi version dependency, and the chain down from them
+    pub fn indirect_for<'a>(
+        &'a self,
+        name: &Name,
+    ) -> impl Iterator<Item = (&'a Package, &'a MultiVerDepBlameChain)> + use<'a> {
+        let name = name.clone();
+        self.0
+            .iter()
+            .filter_map(move |(package, entry)| Some((package, entry.indirect.get(&name)?)))
+    }
+
+    /// Iterates over every package and the multi version dependency names it is directly to blame for
+    pub fn direct_iter(&self) -> impl Iterator<Item = (&Package, &Name)> {
+        self.0
+            .iter()
+            .flat_map(|(package, entry)| entry.direct.keys().map(move |name| (package, name)))
+    }
+
     pub fn direct_count(&self) -> usize {
         self.0
             .values()
@@ -246,3 +608,20 @@ impl MultiVerDepBlame {
         Ok(())
     }
 }
+
+impl Serialize for MultiVerDepBlame {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            package: &'a Package,
+            #[serde(flatten)]
+            blame: &'a MultiVerDepBlameEntry,
+        }
+
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for (package, blame) in &self.0 {
+            seq.serialize_element(&Entry { package, blame })?;
+        }
+        seq.end()
+    }
+}
@@ -0,0 +1,41 @@
+//! Locates the directories cargo keeps in its home: `$CARGO_HOME` or else `~/.cargo`
+
+use std::path::{Path, PathBuf};
+
+use cargo_lock::SourceId;
+
+/// Host of the sparse crates.io index (lock files always name crates.io by its git index)
+const CRATES_IO_SPARSE_HOST: &str = "index.crates.io";
+
+pub(crate) fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::home_dir().map(|home| home.join(".cargo")))
+}
+
+/// The directories of the registry under `dir` (e.g. `registry/index`), in name order. Cargo names
+/// them `<host>-<hash>`, but the hash depends on the cargo version, so only the host is matched: more
+/// than one registry on the same host (or crates.io fetched both via git and sparse) can match.
+pub(crate) fn registry_dirs(dir: &Path, source: &SourceId) -> Vec<PathBuf> {
+    let Some(host) = source.url().host_str() else {
+        return Vec::new();
+    };
+    let is_registry = |name: &str| {
+        name.rsplit_once('-').is_some_and(|(name_host, hash)| {
+            hash.len() == 16
+                && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+                && (name_host == host
+                    || (source.is_default_registry() && name_host == CRATES_IO_SPARSE_HOST))
+        })
+    };
+
+    let mut dirs: Vec<_> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| is_registry(&entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path())
+        .collect();
+    dirs.sort_unstable();
+    dirs
+}
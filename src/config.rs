@@ -0,0 +1,155 @@
+//! Configuration loaded from `depcheck.toml` or the `[workspace.metadata.depcheck]` section of `Cargo.toml`
+
+use std::path::Path;
+
+use cargo_lock::{Name, Version};
+use indexmap::IndexSet;
+use semver::VersionReq;
+use serde::Deserialize;
+
+const CONFIG_FILE: &str = "depcheck.toml";
+const MANIFEST_FILE: &str = "Cargo.toml";
+
+// *** AllowEntry ***
+
+/// A multi version dependency that is tolerated. If versions are given, every version of the dependency
+/// must match at least one of the version requirements for it to be allowed.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AllowEntry {
+    Name(Name),
+    Versions {
+        name: Name,
+        versions: Vec<VersionReq>,
+    },
+}
+
+impl AllowEntry {
+    pub fn allows(&self, name: &Name, versions: &IndexSet<Version>) -> bool {
+        match self {
+            AllowEntry::Name(allow_name) => allow_name == name,
+            AllowEntry::Versions {
+                name: allow_name,
+                versions: reqs,
+            } => {
+                allow_name == name
+                    && versions
+                        .iter()
+                        .all(|version| reqs.iter().any(|req| req.matches(version)))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for AllowEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AllowEntry::Name(name) => write!(f, "{name}"),
+            AllowEntry::Versions { name, versions } => {
+                let versions = versions
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{name} ({versions})")
+            }
+        }
+    }
+}
+
+// *** Config ***
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Multi version dependencies that are tolerated
+    #[serde(default)]
+    allow: Vec<AllowEntry>,
+}
+
+impl Config {
+    /// Load the config from the given path or, if not given, look for `depcheck.toml` and then
+    /// `[workspace.metadata.depcheck]` in `Cargo.toml` in the given directory
+    pub fn load(path: Option<&Path>, dir: &Path) -> Result<Self, String> {
+        match path {
+            Some(path) => Self::from_file(path),
+            None => {
+                let config_path = dir.join(CONFIG_FILE);
+                let manifest_path = dir.join(MANIFEST_FILE);
+
+                if config_path.is_file() {
+                    Self::from_file(&config_path)
+                } else if manifest_path.is_file() {
+                    Self::from_manifest(&manifest_path)
+                } else {
+                    Ok(Self::default())
+                }
+            }
+        }
+    }
+
+    fn read(path: &Path) -> Result<String, String> {
+        std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read '{}': {e}", path.display()))
+    }
+
+    fn from_file(path: &Path) -> Result<Self, String> {
+        toml::from_str(&Self::read(path)?)
+            .map_err(|e| format!("Invalid config file '{}': {e}", path.display()))
+    }
+
+    fn from_manifest(path: &Path) -> Result<Self, String> {
+        #[derive(Deserialize)]
+        struct Manifest {
+            workspace: Option<Workspace>,
+        }
+
+        #[derive(Deserialize)]
+        struct Workspace {
+            metadata: Option<Metadata>,
+        }
+
+        #[derive(Deserialize)]
+        struct Metadata {
+            depcheck: Option<Config>,
+        }
+
+        let manifest: Manifest = toml::from_str(&Self::read(path)?)
+            .map_err(|e| format!("Invalid manifest '{}': {e}", path.display()))?;
+
+        Ok(manifest
+            .workspace
+            .and_then(|workspace| workspace.metadata)
+            .and_then(|metadata| metadata.depcheck)
+            .unwrap_or_default())
+    }
+
+    /// Returns true if any allow entry allows the multi version dependency
+    pub fn is_allowed(&self, name: &Name, versions: &IndexSet<Version>) -> bool {
+        self.allow.iter().any(|entry| entry.allows(name, versions))
+    }
+
+    /// Returns the allow entries that did not allow any of the given multi version dependencies
+    pub fn unused_allow_entries<'a>(
+        &self,
+        multi_ver_deps: impl IntoIterator<Item = (&'a Name, IndexSet<Version>)>,
+    ) -> Vec<&AllowEntry> {
+        let mut used = vec![false; self.allow.len()];
+
+        for (name, versions) in multi_ver_deps {
+            if let Some(idx) = self
+                .allow
+                .iter()
+                .position(|entry| entry.allows(name, &versions))
+            {
+                used[idx] = true;
+            }
+        }
+
+        self.allow
+            .iter()
+            .zip(used)
+            .filter_map(|(entry, used)| (!used).then_some(entry))
+            .collect()
+    }
+}
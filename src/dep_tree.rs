@@ -1,165 +1,314 @@
 use crate::Package;
+use crate::workspace::Workspace;
 
-use cargo_lock::{Dependency, Lockfile, Name, ResolveVersion, Version};
+use cargo_lock::{Dependency, Lockfile, Name, Version};
 use indexmap::{IndexMap, IndexSet};
 
+/// Dense ID of a package (a single version of a dependency) in the dependency graph
+pub type PkgId = usize;
+
 // *** Deps ***
 
+/// The dependency graph. Every package is interned once and referred to by its `PkgId` everywhere
+/// else, so walking the graph never needs to hash or clone names and versions.
 #[derive(Debug)]
 pub struct Deps {
+    /// Packages indexed by ID
+    pkgs: IndexSet<Package>,
+    /// Dependencies and dependents of each package indexed by ID
+    nodes: Vec<DepVersion>,
     deps: IndexMap<Name, Dep>,
-    top_level_deps: IndexSet<Package>,
+    top_level_deps: Vec<PkgId>,
 }
 
 impl Deps {
+    /// Builds the dependency graph from any lock file version. `cargo-lock` already resolves the
+    /// v1/v2 dependency formats (full `name version (source)` strings and abbreviated entries), so
+    /// the only difference left to handle is the legacy `[root]` package of early v1 lock files.
     pub fn from_lock_file(lock_file: Lockfile) -> Result<Self, String> {
-        let mut deps = IndexMap::with_capacity(lock_file.packages.len());
-        let mut top_level_deps = IndexSet::new();
-
-        // I can't find any examples of non-v3/v4 lock files, so I'm not sure if this is necessary
-        if lock_file.version != ResolveVersion::V3 && lock_file.version != ResolveVersion::V4 {
-            return Err("Only v3/v4 lock files are supported".into());
-        }
-
-        for package in lock_file.packages {
-            let top_level = package.source.is_none();
+        let packages: Vec<_> = lock_file
+            .root
+            .into_iter()
+            .chain(lock_file.packages)
+            .collect();
+        let mut deps = Self {
+            pkgs: IndexSet::with_capacity(packages.len()),
+            nodes: Vec::with_capacity(packages.len()),
+            deps: IndexMap::with_capacity(packages.len()),
+            top_level_deps: Vec::new(),
+        };
 
-            if top_level {
-                top_level_deps.insert(Package {
+        // Intern every package first so dependencies can be matched against the full source (the
+        // dependency entries of git packages don't include the commit hash). A package repeated in
+        // the lock file is only added once.
+        let ids: Vec<_> = packages
+            .iter()
+            .map(|package| {
+                let (id, inserted) = deps.intern(Package {
                     name: package.name.clone(),
                     version: package.version.clone(),
+                    source: package.source.clone(),
                 });
-            }
 
-            let dep: &mut Dep = deps.entry(package.name.clone()).or_default();
-            dep.add_modify_ver_dependencies(
-                package.version.clone(),
-                top_level,
-                &package.dependencies,
-            );
+                if inserted && deps.nodes[id].is_top_level() {
+                    deps.top_level_deps.push(id);
+                }
+                inserted.then_some(id)
+            })
+            .collect();
 
-            // Add all dependents
-            for dependency in package.dependencies {
-                let dependent = Package {
-                    name: package.name.clone(),
-                    version: package.version.clone(),
-                };
+        // Add all dependencies and dependents
+        for (package, id) in packages
+            .into_iter()
+            .zip(ids)
+            .filter_map(|(package, id)| Some((package, id?)))
+        {
+            let dependencies = package
+                .dependencies
+                .iter()
+                .map(|dependency| {
+                    let dep_id = deps.dependency_id(dependency)?;
+                    deps.nodes[dep_id].dependents.push(id);
+                    Ok(dep_id)
+                })
+                .collect::<Result<_, String>>()?;
+            deps.nodes[id].dependencies = dependencies;
+        }
 
-                let top_level = dependency.source.is_none();
+        Ok(deps)
+    }
 
-                let dep = deps.entry(dependency.name.clone()).or_default();
-                dep.add_modify_ver_dependent(dependency.version, top_level, dependent);
-            }
+    /// Returns the ID of the package, adding it to the graph if it isn't already present (and whether
+    /// it was added)
+    fn intern(&mut self, pkg: Package) -> (PkgId, bool) {
+        let name = pkg.name.clone();
+        let version = pkg.version.clone();
+        // Without a workspace, guess that every local package is a workspace member
+        let kind = match pkg.source {
+            Some(_) => PackageKind::Remote,
+            None => PackageKind::WorkspaceMember,
+        };
+        let (id, inserted) = self.pkgs.insert_full(pkg);
+
+        if inserted {
+            self.nodes.push(DepVersion::new(kind));
+            self.deps
+                .entry(name)
+                .or_default()
+                .versions
+                .entry(version)
+                .or_default()
+                .push(id);
         }
 
-        Ok(Deps {
-            deps,
-            top_level_deps,
-        })
+        (id, inserted)
     }
 
-    pub fn get_version(&self, pkg: &Package) -> Result<&DepVersion, String> {
-        let dep = self.deps.get(&pkg.name).ok_or(format!(
-            "Corrupted lock file: Dependency '{}' not found",
-            pkg.name
-        ))?;
+    /// Returns the ID of the package matching the dependency's name, version and source
+    fn dependency_id(&self, dependency: &Dependency) -> Result<PkgId, String> {
+        self.deps
+            .get(&dependency.name)
+            .and_then(|dep| dep.versions.get(&dependency.version))
+            .and_then(|ids| {
+                ids.iter()
+                    .copied()
+                    .find(|&id| self.pkgs[id].source == dependency.source)
+            })
+            .ok_or(format!(
+                "Corrupted lock file: Dependency '{dependency}' not found"
+            ))
+    }
+
+    /// Restricts the graph to the packages reachable from the selected workspace members (following
+    /// only the dependency edges the workspace allows), and classifies local packages as workspace
+    /// members or path dependencies based on the workspace (instead of the lack of a source). Fails if
+    /// none of the selected members are in the lock file, as there would be nothing to analyze.
+    pub fn with_workspace(self, workspace: &Workspace) -> Result<Self, String> {
+        let roots: Vec<_> = (0..self.node_count())
+            .filter(|&id| workspace.is_selected(self.package(id)))
+            .collect();
+        if roots.is_empty() {
+            return Err(format!(
+                "None of the selected workspace members ({}) are in the lock file",
+                workspace.selected_names().join(", ")
+            ));
+        }
+
+        Ok(self.retain_reachable(
+            &roots,
+            |pkg| match pkg.source {
+                Some(_) => PackageKind::Remote,
+                None if workspace.is_member(pkg) => PackageKind::WorkspaceMember,
+                None => PackageKind::LocalPath,
+            },
+            |dependent, dependency| workspace.allows_edge(dependent, dependency),
+        ))
+    }
+
+    /// Builds a new graph containing only the packages reachable from the roots via allowed edges
+    fn retain_reachable(
+        &self,
+        roots: &[PkgId],
+        kind: impl Fn(&Package) -> PackageKind,
+        allows_edge: impl Fn(&Package, &Package) -> bool,
+    ) -> Self {
+        let allowed = |id, dep_id| allows_edge(self.package(id), self.package(dep_id));
+        let mut reachable = vec![false; self.node_count()];
+        let mut work_queue = roots.to_vec();
+
+        while let Some(id) = work_queue.pop() {
+            if !std::mem::replace(&mut reachable[id], true) {
+                work_queue.extend(
+                    self.node(id)
+                        .dependencies()
+                        .iter()
+                        .filter(|&&dep_id| allowed(id, dep_id)),
+                );
+            }
+        }
+
+        let mut deps = Self {
+            pkgs: IndexSet::new(),
+            nodes: Vec::new(),
+            deps: IndexMap::new(),
+            top_level_deps: Vec::new(),
+        };
+        // Interning in the original order keeps the relative order of IDs (and so the output) stable
+        let new_ids: Vec<_> = (0..self.node_count())
+            .map(|id| reachable[id].then(|| deps.intern(self.package(id).clone()).0))
+            .collect();
 
-        dep.versions.get(&pkg.version).ok_or(format!(
+        for (id, new_id) in new_ids.iter().enumerate() {
+            if let Some(new_id) = *new_id {
+                deps.nodes[new_id].kind = kind(self.package(id));
+                if deps.nodes[new_id].is_top_level() {
+                    deps.top_level_deps.push(new_id);
+                }
+
+                for &dep_id in self.node(id).dependencies() {
+                    if let Some(new_dep_id) = new_ids[dep_id]
+                        && allowed(id, dep_id)
+                    {
+                        deps.nodes[new_id].dependencies.push(new_dep_id);
+                        deps.nodes[new_dep_id].dependents.push(new_id);
+                    }
+                }
+            }
+        }
+
+        deps
+    }
+
+    pub fn id(&self, pkg: &Package) -> Result<PkgId, String> {
+        self.pkgs.get_index_of(pkg).ok_or(format!(
             "Corrupted lock file: Version '{}' of '{}' not found",
             pkg.version, pkg.name
         ))
     }
 
+    pub fn package(&self, id: PkgId) -> &Package {
+        &self.pkgs[id]
+    }
+
+    pub fn node(&self, id: PkgId) -> &DepVersion {
+        &self.nodes[id]
+    }
+
+    pub fn get_version(&self, pkg: &Package) -> Result<&DepVersion, String> {
+        Ok(self.node(self.id(pkg)?))
+    }
+
+    pub fn get(&self, name: &Name) -> Option<&Dep> {
+        self.deps.get(name)
+    }
+
     pub fn iter(&self) -> impl ExactSizeIterator<Item = (&Name, &Dep)> {
         self.deps.iter()
     }
 
-    pub fn top_level_iter(&self) -> impl ExactSizeIterator<Item = &Package> {
-        self.top_level_deps.iter()
+    pub fn top_level_iter(&self) -> impl ExactSizeIterator<Item = PkgId> {
+        self.top_level_deps.iter().copied()
     }
 
+    /// The number of unique package names
     pub fn count(&self) -> usize {
         self.deps.len()
     }
+
+    /// The number of packages (unique name, version and source), which is also the upper bound of all
+    /// IDs
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
 }
 
 // *** Dep ***
 
 #[derive(Debug, Default)]
 pub struct Dep {
-    versions: IndexMap<Version, DepVersion>,
+    /// IDs of each version (more than one if the same version came from different sources)
+    versions: IndexMap<Version, Vec<PkgId>>,
 }
 
 impl Dep {
+    /// True if there is more than one version, or the same version from more than one source
     pub fn has_multiple_versions(&self) -> bool {
-        self.versions.len() > 1
+        self.versions.values().map(Vec::len).sum::<usize>() > 1
     }
 
     pub fn versions(&self) -> IndexSet<Version> {
         self.versions.keys().cloned().collect()
     }
 
-    fn add_modify_ver_dependencies(
-        &mut self,
-        version: Version,
-        top_level: bool,
-        deps: &[Dependency],
-    ) {
-        self.versions
-            .entry(version)
-            .or_insert_with(|| DepVersion::new(top_level))
-            .add_dependencies(deps);
+    pub fn iter(&self) -> impl Iterator<Item = PkgId> {
+        self.versions.values().flatten().copied()
     }
+}
 
-    fn add_modify_ver_dependent(&mut self, version: Version, top_level: bool, dependent: Package) {
-        self.versions
-            .entry(version)
-            .or_insert_with(|| DepVersion::new(top_level))
-            .add_dependent(dependent);
-    }
+// *** PackageKind ***
+
+/// Where a package lives relative to the workspace
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PackageKind {
+    /// A member of the workspace (these are the top level packages)
+    WorkspaceMember,
+    /// A local package outside of the workspace (e.g. a vendored crate or `path` dependency)
+    LocalPath,
+    /// A registry or git package
+    Remote,
 }
 
 // *** DepVersion ***
 
 #[derive(Debug)]
 pub struct DepVersion {
-    dependencies: IndexSet<Package>,
-    dependents: IndexSet<Package>,
-    top_level: bool,
+    dependencies: Vec<PkgId>,
+    dependents: Vec<PkgId>,
+    kind: PackageKind,
 }
 
 impl DepVersion {
-    pub fn new(top_level: bool) -> Self {
+    pub fn new(kind: PackageKind) -> Self {
         Self {
-            dependencies: IndexSet::new(),
-            dependents: IndexSet::new(),
-            top_level,
+            dependencies: Vec::new(),
+            dependents: Vec::new(),
+            kind,
         }
     }
 
+    pub fn kind(&self) -> PackageKind {
+        self.kind
+    }
+
     pub fn is_top_level(&self) -> bool {
-        self.top_level || self.dependents.is_empty()
+        self.kind == PackageKind::WorkspaceMember
     }
 
-    pub fn dependencies(&self) -> &IndexSet<Package> {
+    pub fn dependencies(&self) -> &[PkgId] {
         &self.dependencies
     }
 
-    pub fn dependents(&self) -> &IndexSet<Package> {
+    pub fn dependents(&self) -> &[PkgId] {
         &self.dependents
     }
-
-    fn add_dependencies(&mut self, deps: &[cargo_lock::Dependency]) {
-        self.dependencies = deps
-            .iter()
-            .map(|dep| Package {
-                name: dep.name.clone(),
-                version: dep.version.clone(),
-            })
-            .collect();
-    }
-
-    fn add_dependent(&mut self, dependent: Package) {
-        self.dependents.insert(dependent);
-    }
 }
@@ -0,0 +1,252 @@
+//! Differences in multi version dependencies and direct blame between two lock files
+
+use crate::results::{JSON_FORMAT_VERSION, MultiVerDepResults};
+use crate::{DIRECT, NO_DUP, Package};
+
+use cargo_lock::Name;
+use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+
+/// Multi version dependency name -> blamed packages (each a single version from a single source)
+type DirectBlame = IndexMap<Name, IndexSet<Package>>;
+
+fn direct_blame(results: &MultiVerDepResults) -> DirectBlame {
+    let mut blame = DirectBlame::new();
+
+    for (package, name) in results.direct_blame_iter() {
+        blame
+            .entry(name.clone())
+            .or_default()
+            .insert(package.clone());
+    }
+
+    blame
+}
+
+fn versions(results: &MultiVerDepResults) -> IndexMap<&Name, IndexSet<Package>> {
+    results
+        .multi_ver_deps()
+        .iter()
+        .map(|(name, mv_dep)| (name, mv_dep.packages().cloned().collect()))
+        .collect()
+}
+
+fn join_versions<'a>(pkgs: impl IntoIterator<Item = &'a Package>) -> String {
+    join(pkgs.into_iter().map(Package::display_version))
+}
+
+fn join<T: std::fmt::Display>(items: impl IntoIterator<Item = T>) -> String {
+    items
+        .into_iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// *** ChangedVersions ***
+
+/// Versions are compared along with their source, so moving a version to another source is a change
+#[derive(Serialize)]
+pub(crate) struct ChangedVersions {
+    gained: IndexSet<Package>,
+    lost: IndexSet<Package>,
+}
+
+// *** BlameChange ***
+
+/// A package (a single version from a single source) that gained or lost direct blame for a multi
+/// version dependency
+#[derive(Serialize)]
+pub(crate) struct BlameChange {
+    name: Name,
+    package: Package,
+}
+
+impl std::fmt::Display for BlameChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} via {}", self.name, self.package)
+    }
+}
+
+// *** DepDiff ***
+
+#[derive(Default, Serialize)]
+pub struct DepDiff {
+    format_version: u32,
+
+    /// Multi version dependencies only present in the new lock file
+    added: IndexMap<Name, IndexSet<Package>>,
+
+    /// Multi version dependencies only present in the old lock file
+    resolved: IndexMap<Name, IndexSet<Package>>,
+
+    /// Multi version dependencies present in both, but with different versions
+    changed: IndexMap<Name, ChangedVersions>,
+
+    /// Direct blame only present in the new lock file
+    blame_added: Vec<BlameChange>,
+
+    /// Direct blame only present in the old lock file
+    blame_removed: Vec<BlameChange>,
+}
+
+impl DepDiff {
+    pub fn build(old: &MultiVerDepResults, new: &MultiVerDepResults) -> Self {
+        let mut diff = Self {
+            format_version: JSON_FORMAT_VERSION,
+            ..Self::default()
+        };
+        let old_deps = versions(old);
+        let new_deps = versions(new);
+
+        for (&name, versions) in &new_deps {
+            match old_deps.get(name) {
+                Some(old_versions) => {
+                    let gained: IndexSet<_> = versions.difference(old_versions).cloned().collect();
+                    let lost: IndexSet<_> = old_versions.difference(versions).cloned().collect();
+
+                    if !gained.is_empty() || !lost.is_empty() {
+                        diff.changed
+                            .insert(name.clone(), ChangedVersions { gained, lost });
+                    }
+                }
+                None => {
+                    diff.added.insert(name.clone(), versions.clone());
+                }
+            }
+        }
+
+        for (&name, versions) in &old_deps {
+            if !new_deps.contains_key(name) {
+                diff.resolved.insert(name.clone(), versions.clone());
+            }
+        }
+
+        let old_blame = direct_blame(old);
+        let new_blame = direct_blame(new);
+        diff.blame_added = Self::blame_changes(&new_blame, &old_blame);
+        diff.blame_removed = Self::blame_changes(&old_blame, &new_blame);
+
+        diff.added.sort_unstable_keys();
+        diff.resolved.sort_unstable_keys();
+        diff.changed.sort_unstable_keys();
+        diff
+    }
+
+    /// Direct blame in `blame` that is not present in `other`
+    fn blame_changes(blame: &DirectBlame, other: &DirectBlame) -> Vec<BlameChange> {
+        let mut changes: Vec<_> = blame
+            .iter()
+            .flat_map(|(name, packages)| {
+                packages
+                    .iter()
+                    .filter(|package| {
+                        !other
+                            .get(name)
+                            .is_some_and(|other_pkgs| other_pkgs.contains(*package))
+                    })
+                    .map(|package| BlameChange {
+                        name: name.clone(),
+                        package: package.clone(),
+                    })
+            })
+            .collect();
+
+        changes
+            .sort_unstable_by(|a, b| a.name.cmp(&b.name).then_with(|| a.package.cmp(&b.package)));
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.resolved.is_empty()
+            && self.changed.is_empty()
+            && self.blame_added.is_empty()
+            && self.blame_removed.is_empty()
+    }
+
+    /// True if the new lock file introduced any duplicates, duplicate versions or direct blame
+    pub fn has_regressions(&self) -> bool {
+        !self.added.is_empty()
+            || self
+                .changed
+                .values()
+                .any(|changed| !changed.gained.is_empty())
+            || !self.blame_added.is_empty()
+    }
+
+    pub fn render_json<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        let json = serde_json::to_string_pretty(self).map_err(|_| std::fmt::Error)?;
+        w.write_str(&json)
+    }
+
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(
+                w,
+                "{NO_DUP}No duplicate dependency changes found.{NO_DUP:#}"
+            );
+        }
+
+        if !self.added.is_empty() {
+            writeln!(w, "Added Duplicate(s):\n")?;
+            for (name, versions) in &self.added {
+                writeln!(w, "{DIRECT}{name} ({}){DIRECT:#}", join_versions(versions))?;
+            }
+            writeln!(w)?;
+        }
+
+        if !self.resolved.is_empty() {
+            writeln!(w, "Resolved Duplicate(s):\n")?;
+            for (name, versions) in &self.resolved {
+                writeln!(w, "{NO_DUP}{name} ({}){NO_DUP:#}", join_versions(versions))?;
+            }
+            writeln!(w)?;
+        }
+
+        if !self.changed.is_empty() {
+            writeln!(w, "Changed Duplicate(s):\n")?;
+            for (name, changed) in &self.changed {
+                let versions = changed
+                    .gained
+                    .iter()
+                    .map(|pkg| format!("{DIRECT}+{}{DIRECT:#}", pkg.display_version()))
+                    .chain(
+                        changed
+                            .lost
+                            .iter()
+                            .map(|pkg| format!("{NO_DUP}-{}{NO_DUP:#}", pkg.display_version())),
+                    );
+                writeln!(w, "{name} ({})", join(versions))?;
+            }
+            writeln!(w)?;
+        }
+
+        if !self.blame_added.is_empty() {
+            writeln!(w, "Added Direct Blame:\n")?;
+            for change in &self.blame_added {
+                writeln!(w, "{DIRECT}{change}{DIRECT:#}")?;
+            }
+            writeln!(w)?;
+        }
+
+        if !self.blame_removed.is_empty() {
+            writeln!(w, "Removed Direct Blame:\n")?;
+            for change in &self.blame_removed {
+                writeln!(w, "{NO_DUP}{change}{NO_DUP:#}")?;
+            }
+            writeln!(w)?;
+        }
+
+        writeln!(w, "Summary:\n")?;
+        writeln!(
+            w,
+            "{} added, {} resolved, {} changed duplicate(s) ({} added, {} removed direct blame)",
+            self.added.len(),
+            self.resolved.len(),
+            self.changed.len(),
+            self.blame_added.len(),
+            self.blame_removed.len()
+        )
+    }
+}
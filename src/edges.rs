@@ -0,0 +1,139 @@
+//! Dependency edge labels from the `cargo metadata` resolve graph. Unlike the lock file, it records
+//! the kind (normal, build or dev) of every dependency, and it only contains the dependencies that are
+//! activated by the requested features and (with `--filter-platform`) whose `cfg(..)` matches the
+//! target platforms, so edges can be filtered by kind, features and platform.
+
+use std::collections::{HashMap, HashSet};
+use std::process::Command;
+
+use crate::workspace::to_name;
+use crate::{DepKind, Package};
+
+use cargo_lock::SourceId;
+use cargo_metadata::{DependencyKind, Metadata};
+
+/// Converts a `cargo metadata` package into the same form as a lock file package
+pub(crate) fn to_package(pkg: &cargo_metadata::Package) -> Result<Package, String> {
+    let source = pkg
+        .source
+        .as_ref()
+        .map(|source| {
+            SourceId::from_url(&source.repr)
+                .map_err(|e| format!("Invalid source '{}' of '{}': {e}", source.repr, pkg.name))
+        })
+        .transpose()?;
+
+    Ok(Package {
+        name: to_name(&pkg.name)?,
+        version: pkg.version.clone(),
+        source,
+    })
+}
+
+impl DepKind {
+    fn from_metadata(kind: DependencyKind) -> Option<Self> {
+        match kind {
+            DependencyKind::Normal => Some(DepKind::Normal),
+            DependencyKind::Build => Some(DepKind::Build),
+            DependencyKind::Development => Some(DepKind::Dev),
+            DependencyKind::Unknown => None,
+        }
+    }
+}
+
+/// The target triple of the host, as reported by `rustc -vV` (honoring `$RUSTC` like cargo does)
+pub fn host_target() -> Result<String, String> {
+    let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = Command::new(&rustc)
+        .arg("-vV")
+        .output()
+        .map_err(|e| format!("Unable to run rustc: {e}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Unable to determine the host target: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(|host| host.trim().to_string())
+        .ok_or("Unable to determine the host target: no 'host' in rustc output".to_string())
+}
+
+// *** EdgeFilter ***
+
+pub struct EdgeFilter {
+    /// Dependent -> dependency -> kinds of the dependency
+    edges: HashMap<Package, HashMap<Package, Vec<DepKind>>>,
+    /// Workspace members, the only dependents whose edges are filtered by kind
+    members: HashSet<Package>,
+    /// Kinds of dependencies of workspace members to follow (all if empty)
+    kinds: Vec<DepKind>,
+}
+
+impl EdgeFilter {
+    /// Labels the edges using the resolve graph of `cargo metadata`. Only the dependencies it contains
+    /// (those activated by its features and platforms) are followed.
+    pub fn from_metadata(metadata: &Metadata, kinds: &[DepKind]) -> Result<Self, String> {
+        let resolve = metadata
+            .resolve
+            .as_ref()
+            .ok_or("The cargo metadata output has no dependency resolve graph")?;
+        let packages: HashMap<_, _> = metadata
+            .packages
+            .iter()
+            .map(|pkg| Ok((&pkg.id, to_package(pkg)?)))
+            .collect::<Result<_, String>>()?;
+        let package = |id| {
+            packages.get(id).ok_or(format!(
+                "Package '{id}' not found in the cargo metadata output"
+            ))
+        };
+
+        let mut edges: HashMap<_, HashMap<_, _>> = HashMap::with_capacity(resolve.nodes.len());
+        for node in &resolve.nodes {
+            let dependencies = edges.entry(package(&node.id)?.clone()).or_default();
+
+            for dep in &node.deps {
+                let dep_kinds = dep
+                    .dep_kinds
+                    .iter()
+                    .filter_map(|info| DepKind::from_metadata(info.kind))
+                    .collect();
+                dependencies.insert(package(&dep.pkg)?.clone(), dep_kinds);
+            }
+        }
+
+        let members = metadata
+            .workspace_members
+            .iter()
+            .map(|id| package(id).cloned())
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            edges,
+            members,
+            kinds: kinds.to_vec(),
+        })
+    }
+
+    /// True if the dependency of a workspace member is of one of the selected kinds. Below the members,
+    /// normal dependencies are always followed (so the dependencies of build dependencies are kept).
+    /// Edges unknown to `cargo metadata` (such as those for other platforms) are never followed.
+    pub fn allows(&self, dependent: &Package, dependency: &Package) -> bool {
+        let is_member = self.members.contains(dependent);
+
+        self.edges
+            .get(dependent)
+            .and_then(|dependencies| dependencies.get(dependency))
+            .is_some_and(|dep_kinds| {
+                self.kinds.is_empty()
+                    || dep_kinds.iter().any(|kind| {
+                        self.kinds.contains(kind) || (!is_member && *kind == DepKind::Normal)
+                    })
+            })
+    }
+}
@@ -0,0 +1,44 @@
+//! Reads lock files from the local git object store without checking anything out
+
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+use cargo_lock::Lockfile;
+
+/// Load the lock file as it was at the given git revision (e.g. `HEAD~1` or `origin/main`). The lock
+/// file's directory determines the repository and the path within it.
+pub fn lock_file_at_rev(lock_path: &Path, rev: &str) -> Result<Lockfile, String> {
+    // The revision ends up in git's arguments, where a leading '-' would make it an option
+    if rev.starts_with('-') {
+        return Err(format!("Invalid git revision '{rev}'"));
+    }
+
+    let file_name = lock_path
+        .file_name()
+        .ok_or(format!("Invalid lock file path '{}'", lock_path.display()))?;
+    let dir = match lock_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    // The './' prefix makes git resolve the path relative to the directory instead of the repo root
+    let object = format!("{rev}:./{}", file_name.to_string_lossy());
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["show", &object])
+        .output()
+        .map_err(|e| format!("Unable to run git: {e}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Unable to read '{object}' from git: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let contents = String::from_utf8(output.stdout)
+        .map_err(|e| format!("Lock file '{object}' is not valid UTF-8: {e}"))?;
+    Lockfile::from_str(&contents).map_err(|e| format!("Invalid lock file '{object}': {e}"))
+}
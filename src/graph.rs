@@ -0,0 +1,209 @@
+//! Generic graph algorithms over dense `usize` node IDs
+
+use std::ops::Range;
+
+// *** BitSet ***
+
+/// A fixed size set of small integers
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct BitSet(Vec<u64>);
+
+impl BitSet {
+    pub fn new(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+
+    pub fn insert(&mut self, bit: usize) {
+        self.0[bit / 64] |= 1 << (bit % 64);
+    }
+
+    pub fn remove(&mut self, bit: usize) {
+        self.0[bit / 64] &= !(1 << (bit % 64));
+    }
+
+    pub fn contains(&self, bit: usize) -> bool {
+        self.0[bit / 64] & (1 << (bit % 64)) != 0
+    }
+
+    /// The bits set within the given range
+    pub fn iter_range(&self, range: Range<usize>) -> impl Iterator<Item = usize> + '_ {
+        range.filter(|&bit| self.contains(bit))
+    }
+
+    pub fn union_with(&mut self, other: &BitSet) {
+        for (word, other_word) in self.0.iter_mut().zip(&other.0) {
+            *word |= other_word;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|word| *word == 0)
+    }
+}
+
+// *** Strongly connected components ***
+
+/// Finds the strongly connected components of the graph (Tarjan's algorithm, iterative so deep graphs
+/// can't overflow the stack). Components are returned in reverse topological order: every component
+/// comes after all the components reachable from it.
+pub(crate) fn strongly_connected_components<'a>(
+    node_count: usize,
+    edges: impl Fn(usize) -> &'a [usize],
+) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+
+    let mut index = vec![UNVISITED; node_count];
+    let mut low_link = vec![0; node_count];
+    let mut on_stack = vec![false; node_count];
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+    let mut next_index = 0;
+
+    // Each frame is a node and the position of the next edge to process
+    let mut call_stack: Vec<(usize, usize)> = Vec::new();
+
+    for root in 0..node_count {
+        if index[root] != UNVISITED {
+            continue;
+        }
+
+        call_stack.push((root, 0));
+
+        while let Some((node, edge_idx)) = call_stack.pop() {
+            if edge_idx == 0 {
+                index[node] = next_index;
+                low_link[node] = next_index;
+                next_index += 1;
+                stack.push(node);
+                on_stack[node] = true;
+            }
+
+            let node_edges = edges(node);
+            if let Some(&next) = node_edges.get(edge_idx) {
+                call_stack.push((node, edge_idx + 1));
+
+                if index[next] == UNVISITED {
+                    call_stack.push((next, 0));
+                } else if on_stack[next] {
+                    low_link[node] = low_link[node].min(index[next]);
+                }
+                continue;
+            }
+
+            // All edges processed - propagate low link to our caller and pop a component if we are its root
+            if let Some(&(parent, _)) = call_stack.last() {
+                low_link[parent] = low_link[parent].min(low_link[node]);
+            }
+
+            if low_link[node] == index[node] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
+    }
+
+    components
+}
+
+// *** Dominators ***
+
+/// Finds the number of nodes each node dominates besides itself: the nodes only reachable from the
+/// roots through it. The roots hang off a virtual root, so a node reachable from two roots is dominated
+/// by neither. Immediate dominators are found with the iterative algorithm of Cooper, Harvey and
+/// Kennedy, and unreachable nodes dominate nothing.
+pub(crate) fn dominated_counts<'a>(
+    node_count: usize,
+    roots: impl IntoIterator<Item = usize>,
+    edges: impl Fn(usize) -> &'a [usize],
+) -> Vec<usize> {
+    const UNDEFINED: usize = usize::MAX;
+    let virtual_root = node_count;
+
+    // Depth first search for the post order (iterative, like above) and the predecessors of each node
+    let mut post_order = Vec::with_capacity(node_count + 1);
+    let mut preds = vec![Vec::new(); node_count];
+    let mut visited = vec![false; node_count];
+    let mut call_stack: Vec<(usize, usize)> = Vec::new();
+
+    for root in roots {
+        preds[root].push(virtual_root);
+        if std::mem::replace(&mut visited[root], true) {
+            continue;
+        }
+
+        call_stack.push((root, 0));
+        while let Some((node, edge_idx)) = call_stack.pop() {
+            if let Some(&next) = edges(node).get(edge_idx) {
+                call_stack.push((node, edge_idx + 1));
+                preds[next].push(node);
+                if !std::mem::replace(&mut visited[next], true) {
+                    call_stack.push((next, 0));
+                }
+            } else {
+                post_order.push(node);
+            }
+        }
+    }
+    post_order.push(virtual_root);
+
+    let mut order = vec![UNDEFINED; node_count + 1];
+    for (idx, &node) in post_order.iter().enumerate() {
+        order[node] = idx;
+    }
+
+    // Walks both nodes up the dominator tree until they meet (a dominator always comes later in post
+    // order)
+    let intersect = |idom: &[usize], mut a: usize, mut b: usize| {
+        while a != b {
+            while order[a] < order[b] {
+                a = idom[a];
+            }
+            while order[b] < order[a] {
+                b = idom[b];
+            }
+        }
+        a
+    };
+
+    let mut idom = vec![UNDEFINED; node_count + 1];
+    idom[virtual_root] = virtual_root;
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        // Reverse post order, skipping the virtual root
+        for &node in post_order.iter().rev().skip(1) {
+            let mut new_idom = UNDEFINED;
+            for &pred in &preds[node] {
+                if idom[pred] != UNDEFINED {
+                    new_idom = match new_idom {
+                        UNDEFINED => pred,
+                        _ => intersect(&idom, pred, new_idom),
+                    };
+                }
+            }
+
+            if idom[node] != new_idom {
+                idom[node] = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    // Every node comes before its dominator in post order, so its count is final when added up
+    let mut counts = vec![0; node_count + 1];
+    for &node in &post_order[..post_order.len() - 1] {
+        counts[idom[node]] += counts[node] + 1;
+    }
+
+    counts.truncate(node_count);
+    counts
+}
@@ -0,0 +1,273 @@
+//! Reads the releases of registry packages from the local index cache cargo keeps for each registry
+//! (`$CARGO_HOME/registry/index/<host>-<hash>/.cache`). Nothing is ever fetched, so only packages cargo
+//! has already resolved at some point are known.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::cargo_home::{cargo_home, registry_dirs};
+
+use cargo_lock::{Name, SourceId, Version};
+use semver::VersionReq;
+use serde::Deserialize;
+
+/// The only version of the cache file format understood
+const CACHE_VERSION: u8 = 3;
+
+/// Path of the package within an index, e.g. `1/a`, `3/s/syn` or `se/rd/serde`
+fn index_path(name: &str) -> PathBuf {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => Path::new("1").join(&name),
+        2 => Path::new("2").join(&name),
+        3 => Path::new("3").join(&name[..1]).join(&name),
+        _ => Path::new(&name[..2]).join(&name[2..4]).join(&name),
+    }
+}
+
+/// Parses a cache file: a header (format version, index format version and the registry's version
+/// of the file) followed by NUL terminated pairs of a version and the JSON line of its release
+fn parse_cache(data: &[u8]) -> Option<Vec<Release>> {
+    let (&version, data) = data.split_first()?;
+    if version != CACHE_VERSION {
+        return None;
+    }
+
+    let mut fields = data.get(4..)?.split(|&byte| byte == 0).skip(1);
+    let mut releases = Vec::new();
+    while let (Some(_), Some(json)) = (fields.next(), fields.next()) {
+        // Skip releases using index features this doesn't understand instead of dropping them all
+        if let Ok(release) = serde_json::from_slice(json) {
+            releases.push(release);
+        }
+    }
+
+    releases.sort_unstable_by(|a: &Release, b| a.vers.cmp(&b.vers));
+    Some(releases)
+}
+
+// *** Release ***
+
+#[derive(Deserialize)]
+struct ReleaseDep {
+    name: String,
+    req: String,
+    /// The real package name if the dependency is renamed
+    package: Option<String>,
+    kind: Option<String>,
+}
+
+/// A single published version of a package and its dependencies
+#[derive(Deserialize)]
+pub(crate) struct Release {
+    vers: Version,
+    deps: Vec<ReleaseDep>,
+    #[serde(default)]
+    yanked: bool,
+}
+
+impl Release {
+    pub fn version(&self) -> &Version {
+        &self.vers
+    }
+
+    pub fn is_yanked(&self) -> bool {
+        self.yanked
+    }
+
+    /// The requirements on the package (dev dependencies of registry packages are never resolved,
+    /// so they are skipped). More than one is possible when declared separately per platform.
+    pub fn requirements(&self, name: &Name) -> impl Iterator<Item = VersionReq> {
+        self.deps
+            .iter()
+            .filter(|dep| dep.kind.as_deref() != Some("dev"))
+            .filter(move |dep| dep.package.as_deref().unwrap_or(&dep.name) == name.as_str())
+            // An unparsable requirement can't match anything, so treat it as absent
+            .filter_map(|dep| VersionReq::parse(&dep.req).ok())
+    }
+}
+
+// *** RegistryIndex ***
+
+pub struct RegistryIndex {
+    /// The cache directory given explicitly, used for every registry
+    cache_dir: Option<PathBuf>,
+    /// The `registry/index` directory of the cargo home, holding a directory per registry
+    index_dir: Option<PathBuf>,
+
+    /// Releases of the packages already looked up (`None` if not in any cache)
+    packages: HashMap<(SourceId, Name), Option<Vec<Release>>>,
+}
+
+impl RegistryIndex {
+    /// Uses the given index cache directory, or else the cache of each package's registry in the
+    /// cargo home
+    pub fn new(cache_dir: Option<&Path>) -> Self {
+        Self {
+            cache_dir: cache_dir.map(Path::to_path_buf),
+            index_dir: cargo_home().map(|home| home.join("registry").join("index")),
+            packages: HashMap::new(),
+        }
+    }
+
+    /// The cache directories to search for packages of the registry, in order
+    fn cache_dirs(&self, source: &SourceId) -> Vec<PathBuf> {
+        match (&self.cache_dir, &self.index_dir) {
+            (Some(cache_dir), _) => vec![cache_dir.clone()],
+            (None, Some(index_dir)) => registry_dirs(index_dir, source)
+                .into_iter()
+                .map(|dir| dir.join(".cache"))
+                .collect(),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    fn load(&self, name: &Name, source: &SourceId) -> Option<Vec<Release>> {
+        let path = index_path(name.as_str());
+        self.cache_dirs(source)
+            .iter()
+            .find_map(|dir| parse_cache(&std::fs::read(dir.join(&path)).ok()?))
+    }
+
+    /// All known releases of the package of the registry in version order (empty if it isn't in the
+    /// cache)
+    pub(crate) fn releases(&mut self, name: &Name, source: &SourceId) -> &[Release] {
+        let key = (source.clone(), name.clone());
+        if !self.packages.contains_key(&key) {
+            let releases = self.load(name, source);
+            self.packages.insert(key.clone(), releases);
+        }
+
+        self.packages[&key].as_deref().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn fixture_index() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/index")
+    }
+
+    fn fixture_cache(name: &str) -> Vec<u8> {
+        std::fs::read(fixture_index().join(index_path(name))).unwrap()
+    }
+
+    #[test]
+    fn cache_of_another_version_is_ignored() {
+        let mut data = fixture_cache("tokio-rustls");
+        assert!(parse_cache(&data).is_some());
+
+        data[0] = CACHE_VERSION + 1;
+        assert!(parse_cache(&data).is_none());
+    }
+
+    #[test]
+    fn corrupt_cache_is_ignored() {
+        let data = fixture_cache("tokio-rustls");
+        let truncated = parse_cache(&data[..data.len() / 2]).unwrap();
+        assert!(truncated.len() < 5);
+
+        assert!(parse_cache(&[]).is_none());
+        assert!(parse_cache(&[CACHE_VERSION, 2]).is_none());
+        for json in [&b"{"[..], b"[]", &[0xff, 0xfe]] {
+            let data = [&[CACHE_VERSION, 2, 0, 0, 0, 0, b'1', 0], json, &[0]].concat();
+            assert!(parse_cache(&data).unwrap().is_empty());
+        }
+    }
+
+    /// Creates an empty `registry/index` directory unique to the test run
+    fn scratch_index_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-depcheck-{}-{test}", std::process::id()));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes the cache file of the package into the registry's directory of the index directory
+    fn write_cache(index_dir: &Path, registry: &str, name: &str, data: &[u8]) {
+        let path = index_dir
+            .join(registry)
+            .join(".cache")
+            .join(index_path(name));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, data).unwrap();
+    }
+
+    fn index_of(index_dir: &Path) -> RegistryIndex {
+        RegistryIndex {
+            cache_dir: None,
+            index_dir: Some(index_dir.to_path_buf()),
+            packages: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn stale_cache_falls_through_to_the_next_one() {
+        let index_dir = scratch_index_dir("stale_cache_falls_through_to_the_next_one");
+        let mut stale = fixture_cache("tokio-rustls");
+        stale[0] = CACHE_VERSION + 1;
+        // crates.io fetched via git before and via the sparse protocol since
+        write_cache(
+            &index_dir,
+            "github.com-1ecc6299db9ec823",
+            "tokio-rustls",
+            &stale,
+        );
+        write_cache(
+            &index_dir,
+            "index.crates.io-1949cf8c6b5b557f",
+            "tokio-rustls",
+            &fixture_cache("tokio-rustls"),
+        );
+
+        let crates_io = SourceId::default();
+        let name = Name::from_str("tokio-rustls").unwrap();
+        assert_eq!(index_of(&index_dir).releases(&name, &crates_io).len(), 5);
+
+        let stale_dir = index_dir.join("github.com-1ecc6299db9ec823").join(".cache");
+        let mut index = RegistryIndex::new(Some(&stale_dir));
+        assert!(index.releases(&name, &crates_io).is_empty());
+    }
+
+    #[test]
+    fn releases_are_read_from_the_cache_of_the_package_registry() {
+        let index_dir =
+            scratch_index_dir("releases_are_read_from_the_cache_of_the_package_registry");
+        write_cache(
+            &index_dir,
+            "index.crates.io-1949cf8c6b5b557f",
+            "tokio-rustls",
+            &fixture_cache("tokio-rustls"),
+        );
+        let release = br#"{"name":"tokio-rustls","vers":"0.1.0","deps":[]}"#;
+        let data = [&[CACHE_VERSION, 2, 0, 0, 0, 0, b'1', 0], &release[..], &[0]].concat();
+        write_cache(
+            &index_dir,
+            "my-registry.example.com-0123456789abcdef",
+            "tokio-rustls",
+            &data,
+        );
+
+        let source = |url| SourceId::from_url(url).unwrap();
+        let name = Name::from_str("tokio-rustls").unwrap();
+        let mut index = index_of(&index_dir);
+        assert_eq!(index.releases(&name, &SourceId::default()).len(), 5);
+
+        let releases = index.releases(
+            &name,
+            &source("registry+https://my-registry.example.com/index"),
+        );
+        assert_eq!(releases.len(), 1);
+        assert_eq!(releases[0].version().to_string(), "0.1.0");
+
+        let releases = index.releases(&name, &source("sparse+https://other.example.com/index/"));
+        assert!(releases.is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+//! Inverse dependency trees (similar to `cargo tree -i`) starting at each version of a multi version
+//! dependency and walking the dependents up to the top level packages.
+
+use crate::Package;
+use crate::dep_tree::{Deps, PkgId};
+use crate::multi_ver_deps::MultiVerDeps;
+
+use serde::Serialize;
+
+// *** InverseTreeNode ***
+
+/// A single package in an inverse tree along with the packages that depend on it
+#[derive(Serialize)]
+pub(crate) struct InverseTreeNode {
+    package: Package,
+    dependents: Vec<InverseTreeNode>,
+    /// True if this node's dependents were already displayed elsewhere in the same tree
+    repeated: bool,
+    /// True if this node's dependents were cut off by the depth limit
+    truncated: bool,
+}
+
+impl InverseTreeNode {
+    fn build(
+        id: PkgId,
+        deps: &Deps,
+        depth: usize,
+        max_depth: Option<usize>,
+        visited: &mut [bool],
+    ) -> Self {
+        let dep_ver = deps.node(id);
+        let mut node = Self {
+            package: deps.package(id).clone(),
+            dependents: Vec::new(),
+            repeated: false,
+            truncated: false,
+        };
+
+        if dep_ver.dependents().is_empty() {
+            return node;
+        }
+        if max_depth.is_some_and(|max| depth >= max) {
+            node.truncated = true;
+            return node;
+        }
+
+        // Only expand a given package once per tree - this keeps output small and also
+        // guarantees we terminate if the lock file happens to have a cycle
+        if std::mem::replace(&mut visited[id], true) {
+            node.repeated = true;
+            return node;
+        }
+
+        let mut dependents = dep_ver.dependents().to_vec();
+        dependents.sort_unstable_by_key(|&dependent| deps.package(dependent));
+
+        for dependent in dependents {
+            node.dependents
+                .push(Self::build(dependent, deps, depth + 1, max_depth, visited));
+        }
+
+        node
+    }
+
+    fn render<W: std::fmt::Write>(
+        &self,
+        w: &mut W,
+        prefix: &mut String,
+        last: Option<bool>,
+    ) -> std::fmt::Result {
+        match last {
+            Some(true) => write!(w, "{prefix}└── ")?,
+            Some(false) => write!(w, "{prefix}├── ")?,
+            None => {}
+        }
+
+        write!(w, "{}", self.package)?;
+        if self.repeated {
+            write!(w, " (*)")?;
+        }
+        if self.truncated {
+            write!(w, " ...")?;
+        }
+        writeln!(w)?;
+
+        let prefix_len = prefix.len();
+        match last {
+            Some(true) => prefix.push_str("    "),
+            Some(false) => prefix.push_str("│   "),
+            None => {}
+        }
+
+        for (idx, dependent) in self.dependents.iter().enumerate() {
+            dependent.render(w, prefix, Some(idx == self.dependents.len() - 1))?;
+        }
+
+        prefix.truncate(prefix_len);
+        Ok(())
+    }
+}
+
+// *** InverseTrees ***
+
+/// One inverse tree for every version of every multi version dependency
+#[derive(Serialize)]
+pub struct InverseTrees(Vec<InverseTreeNode>);
+
+impl InverseTrees {
+    pub fn build(deps: &Deps, multi_ver_deps: &MultiVerDeps, max_depth: Option<usize>) -> Self {
+        let mut trees = Vec::with_capacity(multi_ver_deps.dup_ver_count());
+
+        for (_, mv_dep) in multi_ver_deps.iter() {
+            for id in mv_dep.ids() {
+                let mut visited = vec![false; deps.node_count()];
+                trees.push(InverseTreeNode::build(id, deps, 0, max_depth, &mut visited));
+            }
+        }
+
+        Self(trees)
+    }
+
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        let mut prefix = String::new();
+
+        for tree in &self.0 {
+            tree.render(w, &mut prefix, None)?;
+            writeln!(w)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,21 +1,55 @@
+use std::hash::{Hash, Hasher};
+
 use anstyle::{AnsiColor, Style};
-use cargo_lock::{Name, Version};
+use cargo_lock::{Name, SourceId, Version};
 use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 
 pub(crate) const DIRECT: Style = AnsiColor::Red.on_default();
 pub(crate) const INDIRECT: Style = AnsiColor::Yellow.on_default();
 pub(crate) const NO_DUP: Style = AnsiColor::Green.on_default();
+pub(crate) const ALLOWED: Style = AnsiColor::Cyan.on_default();
 
+pub(crate) mod baseline;
 pub(crate) mod blame;
+pub(crate) mod cargo_home;
+pub(crate) mod config;
 pub(crate) mod dep_tree;
+pub(crate) mod diff;
+pub(crate) mod edges;
+pub(crate) mod git;
+pub(crate) mod graph;
+pub(crate) mod index;
+pub(crate) mod inverse_tree;
 pub(crate) mod multi_ver_deps;
 pub(crate) mod multi_ver_parents;
+pub(crate) mod plan;
+#[cfg(test)]
+pub(crate) mod random_locks;
+pub(crate) mod requirements;
 pub(crate) mod results;
+pub(crate) mod root_causes;
+pub(crate) mod upgrades;
+pub(crate) mod why;
+pub(crate) mod workspace;
 
+pub use baseline::Baseline;
+pub use config::{AllowEntry, Config};
 pub use dep_tree::*;
-pub use multi_ver_deps::MultiVerDeps;
+pub use diff::DepDiff;
+pub use edges::{EdgeFilter, host_target};
+pub use git::lock_file_at_rev;
+pub use index::RegistryIndex;
+pub use inverse_tree::InverseTrees;
+pub use multi_ver_deps::{DuplicateKind, MultiVerDeps, SemverCompat, is_semver_compatible};
 pub use multi_ver_parents::MultiVerDepParents;
-pub use results::MultiVerDepResults;
+pub use plan::UpgradePlan;
+pub use requirements::Requirements;
+pub use results::{JSON_FORMAT_VERSION, MultiVerDepResults};
+pub use root_causes::RootCauses;
+pub use upgrades::Upgrades;
+pub use why::Why;
+pub use workspace::Workspace;
 
 // FIXME: Pulls in  clap dependency into library - not ideal, but works for now
 #[derive(Copy, Clone, Eq, PartialEq, ValueEnum)]
@@ -26,10 +60,61 @@ pub enum BlameMode {
     All,
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+// FIXME: Pulls in  clap dependency into library - not ideal, but works for now
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum DepKind {
+    /// Regular dependencies that end up in the built artifacts
+    Normal,
+    /// Dependencies of build scripts
+    Build,
+    /// Dependencies of tests, examples and benchmarks
+    Dev,
+}
+
+// FIXME: Pulls in  clap dependency into library - not ideal, but works for now
+#[derive(Copy, Clone, Default, Eq, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text output
+    #[default]
+    Text,
+    /// Machine readable JSON output (see `schema/output-v1.json`)
+    Json,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Package {
     pub name: Name,
     pub version: Version,
+    /// Where the package came from (registry, git, etc.) - `None` for local packages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceId>,
+}
+
+impl Package {
+    /// The source, unless it is crates.io (which is implied when not shown)
+    pub fn display_source(&self) -> Option<&SourceId> {
+        self.source
+            .as_ref()
+            .filter(|source| !source.is_default_registry())
+    }
+
+    /// The version followed by the source (if not crates.io or local)
+    pub fn display_version(&self) -> String {
+        match self.display_source() {
+            Some(source) => format!("{} ({source})", self.version),
+            None => self.version.to_string(),
+        }
+    }
+}
+
+/// Sources are equal regardless of the git commit when only one of them has it (dependency entries of
+/// git packages leave it out), but `SourceId` hashes the commit, so only the URL is hashed
+impl Hash for Package {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.version.hash(state);
+        self.source.as_ref().map(SourceId::url).hash(state);
+    }
 }
 
 impl Ord for Package {
@@ -37,6 +122,7 @@ impl Ord for Package {
         self.name
             .cmp(&other.name)
             .then_with(|| self.version.cmp(&other.version))
+            .then_with(|| self.source.cmp(&other.source))
     }
 }
 
@@ -48,6 +134,6 @@ impl PartialOrd for Package {
 
 impl std::fmt::Display for Package {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {}", self.name, self.version)
+        write!(f, "{} {}", self.name, self.display_version())
     }
 }
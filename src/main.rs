@@ -1,7 +1,13 @@
 use anstream::println;
-use cargo_depcheck::{BlameMode, Deps, MultiVerDepParents, MultiVerDepResults, MultiVerDeps};
+use cargo_depcheck::{
+    Baseline, BlameMode, Config, DepDiff, DepKind, Deps, EdgeFilter, InverseTrees,
+    MultiVerDepParents, MultiVerDepResults, MultiVerDeps, OutputFormat, RegistryIndex,
+    Requirements, Why, Workspace, host_target, lock_file_at_rev,
+};
 use cargo_lock::Lockfile;
-use clap::Parser;
+use cargo_metadata::CargoOpt;
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
 
 // TODO: Make this different sizes based on collection size?
 const BUFFER_SIZE: usize = 32768;
@@ -14,11 +20,35 @@ const BUFFER_SIZE: usize = 32768;
     long_about = None,
     styles = clap_cargo::style::CLAP_STYLING
 )]
-
 struct CargoCli {
-    /// Path to Cargo.lock
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to Cargo.lock [default: Cargo.lock in the workspace root]
     #[arg(long, short)]
-    lock_path: Option<std::path::PathBuf>,
+    lock_path: Option<PathBuf>,
+
+    #[command(flatten)]
+    manifest: clap_cargo::Manifest,
+
+    #[command(flatten)]
+    workspace: clap_cargo::Workspace,
+
+    #[command(flatten)]
+    features: clap_cargo::Features,
+
+    /// Only follow dependencies of these kinds, e.g. `normal,build` (uses `cargo metadata --offline`)
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "against")]
+    kind: Vec<DepKind>,
+
+    /// Only follow dependencies that build on this target triple (or `host`), can be repeated (uses
+    /// `cargo metadata --offline`)
+    #[arg(long, value_name = "TRIPLE", conflicts_with = "against")]
+    target: Vec<String>,
+
+    /// Path to config file [default: depcheck.toml or Cargo.toml next to Cargo.lock]
+    #[arg(long)]
+    config: Option<PathBuf>,
 
     /// Display packages that are to blame for multi version dependencies
     #[arg(long, short, value_enum)]
@@ -27,44 +57,369 @@ struct CargoCli {
     /// Display the multi version dependency names that each package is responsible for
     #[arg(long, short = 'd')]
     blame_detail: bool,
+
+    /// Directory of vendored sources (from `cargo vendor`) to read dependency requirements from, in
+    /// addition to the cargo registry cache and local manifests
+    #[arg(long, value_name = "DIR")]
+    vendor_dir: Option<PathBuf>,
+
+    /// Suggest upgrades of packages that depend on older duplicate versions (uses the local registry
+    /// index cache, never the network)
+    #[arg(long)]
+    upgrades: bool,
+
+    /// Registry index cache directory to look up upgrades in [default: the index cache of each
+    /// package's registry in `$CARGO_HOME/registry/index`]
+    #[arg(long, value_name = "DIR", requires = "upgrades")]
+    index_dir: Option<PathBuf>,
+
+    /// Display a ranked plan of package bumps (or dropped top level dependencies) that would remove
+    /// every duplicate
+    #[arg(long)]
+    plan: bool,
+
+    /// Only fail on duplicates or direct blame not recorded in this baseline file
+    #[arg(long, conflicts_with = "write_baseline")]
+    baseline: Option<PathBuf>,
+
+    /// Record the current duplicates and direct blame to this baseline file
+    #[arg(long)]
+    write_baseline: Option<PathBuf>,
+
+    /// Diff the lock file against its contents at this git revision (e.g. origin/main)
+    #[arg(long, conflicts_with_all = ["baseline", "write_baseline"])]
+    against: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+
+    /// Display an inverse dependency tree for each version of every multi version dependency
+    #[arg(long, short)]
+    tree: bool,
+
+    /// Maximum depth of the inverse dependency trees
+    #[arg(long, requires = "tree")]
+    depth: Option<usize>,
+}
+
+impl CargoCli {
+    /// True if any workspace related flags were given
+    fn has_workspace_flags(&self) -> bool {
+        self.manifest.manifest_path.is_some()
+            || !self.workspace.package.is_empty()
+            || self.workspace.workspace
+            || self.workspace.all
+            || !self.workspace.exclude.is_empty()
+    }
+
+    /// True if any flags that need the dependency graph from `cargo metadata` were given
+    fn has_edge_flags(&self) -> bool {
+        !self.kind.is_empty()
+            || !self.target.is_empty()
+            || self.features != clap_cargo::Features::default()
+    }
+
+    /// Discovers the workspace via `cargo metadata`. If only a lock file was given (or there is a lock
+    /// file but no manifest in the current directory), the manifest next to it is parsed instead (if
+    /// there is none, top level packages are those without a source).
+    fn workspace(&self) -> Result<Option<Workspace>, Box<dyn std::error::Error>> {
+        let filter_edges = self.has_edge_flags();
+
+        if !self.has_workspace_flags() && !filter_edges {
+            match (&self.command, &self.lock_path) {
+                // Lock files being compared may come from anywhere, so there is no manifest
+                (Some(Command::Diff { .. }), _) => return Ok(None),
+                (_, Some(lock_path)) => {
+                    return Ok(Workspace::from_manifest(&lock_file_dir(lock_path))?);
+                }
+                (_, None) => {
+                    let lock_path = Path::new("Cargo.lock");
+                    // `cargo metadata` would look for a manifest in the parent directories instead
+                    if lock_path.is_file() && !Path::new("Cargo.toml").is_file() {
+                        return Ok(None);
+                    }
+
+                    return match self.metadata_workspace(false) {
+                        Ok(workspace) => Ok(Some(workspace)),
+                        Err(e) if lock_path.is_file() => {
+                            eprintln!(
+                                "Warning: Unable to discover the workspace, reading Cargo.toml instead: {e}"
+                            );
+                            Ok(Workspace::from_manifest(Path::new("."))?)
+                        }
+                        Err(e) => Err(e),
+                    };
+                }
+            }
+        }
+
+        self.metadata_workspace(filter_edges).map(Some)
+    }
+
+    /// Discovers the workspace via `cargo metadata`, along with the dependency edges to follow if
+    /// filtering edges
+    fn metadata_workspace(
+        &self,
+        filter_edges: bool,
+    ) -> Result<Workspace, Box<dyn std::error::Error>> {
+        // Edge labels come from the current workspace, so they can't be applied to other lock files
+        let diffing = matches!(self.command, Some(Command::Diff { .. })) || self.against.is_some();
+        if filter_edges && diffing {
+            return Err("--kind, --target and feature flags can't be used when diffing".into());
+        }
+
+        let mut command = self.manifest.metadata();
+        if let (None, Some(lock_path)) = (&self.manifest.manifest_path, &self.lock_path) {
+            command.manifest_path(lock_file_dir(lock_path).join("Cargo.toml"));
+        }
+        if filter_edges {
+            // The full resolve graph is needed to label edges. Without feature flags, enable all
+            // features so it matches the lock file. Never touch the network or the lock file.
+            let mut options = vec!["--offline".to_string(), "--locked".to_string()];
+            for target in &self.target {
+                let target = match target.as_str() {
+                    "host" => host_target()?,
+                    target => target.to_string(),
+                };
+                options.extend(["--filter-platform".to_string(), target]);
+            }
+
+            if self.features == clap_cargo::Features::default() {
+                command.features(CargoOpt::AllFeatures);
+            } else {
+                self.features.forward_metadata(&mut command);
+            }
+            command.other_options(options);
+        } else {
+            command.no_deps();
+        }
+        let metadata = command.exec()?;
+        let members = metadata.workspace_packages();
+        let unknown = self
+            .workspace
+            .package
+            .iter()
+            .find(|name| !members.iter().any(|pkg| pkg.name.as_str() == name.as_str()));
+        if let Some(name) = unknown {
+            return Err(format!("Package '{name}' is not a member of the workspace").into());
+        }
+
+        let (selected, _) = self.workspace.partition_packages(&metadata);
+        let mut workspace = Workspace::from_metadata(&metadata, &selected)?;
+        if filter_edges {
+            workspace = workspace.with_edges(EdgeFilter::from_metadata(&metadata, &self.kind)?);
+        }
+        Ok(workspace)
+    }
+
+    fn lock_path(&self, workspace: Option<&Workspace>) -> PathBuf {
+        match (&self.lock_path, workspace) {
+            (Some(lock_path), _) => lock_path.clone(),
+            (None, Some(workspace)) => workspace.lock_path(),
+            (None, None) => PathBuf::from("Cargo.lock"),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compare the duplicate dependencies and direct blame of two lock files
+    Diff {
+        /// Path to the old Cargo.lock
+        old: PathBuf,
+
+        /// Path to the new Cargo.lock
+        new: PathBuf,
+    },
+
+    /// Explain why a package is in the lock file: the paths from each workspace member to it and the
+    /// packages to blame for its duplicate versions
+    Why {
+        /// The package as `name` or `name@version` (a partial version like `1` matches all 1.x.y)
+        spec: String,
+
+        /// Maximum number of paths shown per version (shortest first)
+        #[arg(long, default_value_t = 50)]
+        max_paths: usize,
+    },
 }
 
-fn load_and_process_lock_file(
+fn lock_file_dir(lock_path: &Path) -> PathBuf {
+    match lock_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn analyze_lock_file(
     cli: &CargoCli,
-) -> Result<(MultiVerDepResults, String), Box<dyn std::error::Error>> {
-    let lock_path = cli
-        .lock_path
-        .clone()
-        .unwrap_or(std::path::PathBuf::from("Cargo.lock"));
-    let lock_file = Lockfile::load(lock_path)?;
-
-    let deps = Deps::from_lock_file(lock_file)?;
+    lock_file: Lockfile,
+    workspace: Option<&Workspace>,
+    config: &Config,
+    blame_mode: Option<BlameMode>,
+) -> Result<(Deps, MultiVerDepResults), Box<dyn std::error::Error>> {
+    let mut deps = Deps::from_lock_file(lock_file)?;
+    if let Some(workspace) = workspace {
+        deps = deps.with_workspace(workspace)?;
+    }
     // Finding just duplicate packages with no other information is cheap, always do it
-    let multi_ver_deps = MultiVerDeps::from_deps(&deps);
+    let multi_ver_deps = MultiVerDeps::from_deps(&deps, config)?;
+
+    let trees = if cli.tree {
+        Some(InverseTrees::build(&deps, &multi_ver_deps, cli.depth))
+    } else {
+        None
+    };
 
-    // Only blame uses multi version parents, so don't build if we don't need to
-    let multi_ver_parents = if cli.blame.is_some() {
+    // Only blame and the plan use multi version parents, so don't build if we don't need to
+    let multi_ver_parents = if blame_mode.is_some() || cli.plan {
         MultiVerDepParents::build(&deps, &multi_ver_deps)?
     } else {
         MultiVerDepParents::default()
     };
 
-    let results = MultiVerDepResults::build(&deps, &multi_ver_parents, multi_ver_deps, cli.blame)?;
+    let mut results =
+        MultiVerDepResults::build(&deps, &multi_ver_parents, multi_ver_deps, trees, blame_mode)?;
+    if cli.plan {
+        results.build_plan(&deps, &multi_ver_parents);
+    }
+
+    Ok((deps, results))
+}
+
+/// Warns about allow entries that are no longer needed. Only the lock file the config was loaded for
+/// should be checked, or else entries for the other lock file are reported as well.
+fn warn_unused_allow_entries(config: &Config, results: &MultiVerDepResults) {
+    for entry in results.unused_allow_entries(config) {
+        eprintln!("Warning: Allow entry '{entry}' did not match any duplicate dependency");
+    }
+}
+
+fn check(
+    cli: &CargoCli,
+    workspace: Option<&Workspace>,
+) -> Result<(bool, String), Box<dyn std::error::Error>> {
+    let lock_path = cli.lock_path(workspace);
+    let config = Config::load(cli.config.as_deref(), &lock_file_dir(&lock_path))?;
+    let lock_file = Lockfile::load(&lock_path)?;
+    let (deps, mut results) = analyze_lock_file(cli, lock_file, workspace, &config, cli.blame)?;
+    warn_unused_allow_entries(&config, &results);
+    let count = deps.count();
+
+    if cli.blame.is_some() {
+        results.resolve_requirements(&mut Requirements::new(workspace, cli.vendor_dir.as_deref()));
+    }
+    if cli.upgrades {
+        results.suggest_upgrades(&mut RegistryIndex::new(cli.index_dir.as_deref()));
+    }
+
+    let baseline = if let Some(path) = &cli.write_baseline {
+        let baseline = results.baseline();
+        baseline.save(path)?;
+        Some(baseline)
+    } else if let Some(path) = &cli.baseline {
+        Some(Baseline::load(path)?)
+    } else {
+        None
+    };
+
+    if let Some(baseline) = &baseline {
+        results.apply_baseline(baseline);
+    }
+
+    let mut buffer = String::with_capacity(BUFFER_SIZE);
+    match cli.format {
+        OutputFormat::Text => results.render(&mut buffer, count, cli.blame, cli.blame_detail)?,
+        OutputFormat::Json => results.render_json(&mut buffer, count, cli.blame)?,
+    }
+
+    Ok((results.return_error(cli.blame), buffer))
+}
+
+fn why(
+    cli: &CargoCli,
+    workspace: Option<&Workspace>,
+    spec: &str,
+    max_paths: usize,
+) -> Result<(bool, String), Box<dyn std::error::Error>> {
+    let lock_path = cli.lock_path(workspace);
+    let config = Config::load(cli.config.as_deref(), &lock_file_dir(&lock_path))?;
+    let lock_file = Lockfile::load(&lock_path)?;
+    // Blame for any package can be asked for, not just top level ones
+    let (deps, mut results) =
+        analyze_lock_file(cli, lock_file, workspace, &config, Some(BlameMode::All))?;
+    warn_unused_allow_entries(&config, &results);
+    results.resolve_requirements(&mut Requirements::new(workspace, cli.vendor_dir.as_deref()));
+    let why = Why::build(&deps, &results, spec, max_paths)?;
 
     let mut buffer = String::with_capacity(BUFFER_SIZE);
-    results.render(&mut buffer, deps.count(), cli.blame, cli.blame_detail)?;
+    match cli.format {
+        OutputFormat::Text => why.render(&mut buffer)?,
+        OutputFormat::Json => why.render_json(&mut buffer)?,
+    }
 
-    Ok((results, buffer))
+    // This only explains, so it never fails
+    Ok((false, buffer))
+}
+
+fn diff(
+    cli: &CargoCli,
+    workspace: Option<&Workspace>,
+    old_lock_file: Lockfile,
+    new_path: &Path,
+) -> Result<(bool, String), Box<dyn std::error::Error>> {
+    // Blame is needed to report blame changes, so default to all if not specified
+    let blame_mode = Some(cli.blame.unwrap_or(BlameMode::All));
+    let config = Config::load(cli.config.as_deref(), &lock_file_dir(new_path))?;
+    let new_lock_file = Lockfile::load(new_path)?;
+    let (_, old_results) = analyze_lock_file(cli, old_lock_file, workspace, &config, blame_mode)?;
+    let (_, new_results) = analyze_lock_file(cli, new_lock_file, workspace, &config, blame_mode)?;
+    // The config lives next to the new lock file, so entries are only checked against it
+    warn_unused_allow_entries(&config, &new_results);
+    let diff = DepDiff::build(&old_results, &new_results);
+
+    let mut buffer = String::with_capacity(BUFFER_SIZE);
+    match cli.format {
+        OutputFormat::Text => diff.render(&mut buffer)?,
+        OutputFormat::Json => diff.render_json(&mut buffer)?,
+    }
+
+    Ok((diff.has_regressions(), buffer))
 }
 
 fn main() {
-    let cli = CargoCli::parse();
+    // When run as `cargo depcheck`, cargo passes the subcommand name as the first argument. It is
+    // skipped so the binary can also be run directly as `cargo-depcheck`.
+    let args = std::env::args_os()
+        .enumerate()
+        .filter(|(idx, arg)| *idx != 1 || arg != "depcheck")
+        .map(|(_, arg)| arg);
+    let cli = CargoCli::parse_from(args);
+
+    let result = cli.workspace().and_then(|workspace| {
+        let workspace = workspace.as_ref();
+
+        match (&cli.command, &cli.against) {
+            (Some(Command::Diff { old, new }), _) => Lockfile::load(old)
+                .map_err(Into::into)
+                .and_then(|old| diff(&cli, workspace, old, new)),
+            (Some(Command::Why { spec, max_paths }), _) => why(&cli, workspace, spec, *max_paths),
+            (None, Some(rev)) => {
+                let lock_path = cli.lock_path(workspace);
+                lock_file_at_rev(&lock_path, rev)
+                    .map_err(Into::into)
+                    .and_then(|old| diff(&cli, workspace, old, &lock_path))
+            }
+            (None, None) => check(&cli, workspace),
+        }
+    });
 
-    match load_and_process_lock_file(&cli) {
-        Ok((dup_dep_results, buffer)) => {
+    match result {
+        Ok((error, buffer)) => {
             println!("{buffer}");
 
-            if dup_dep_results.return_error(cli.blame) {
+            if error {
                 std::process::exit(1);
             }
         }
@@ -1,85 +1,712 @@
-use crate::dep_tree::Deps;
+use std::cmp::{Ordering, Reverse};
 
-use cargo_lock::{Name, Version};
+use crate::Package;
+use crate::config::Config;
+use crate::dep_tree::{Dep, Deps, PkgId};
+use crate::graph::{self, BitSet};
+
+use cargo_lock::{Name, SourceId, Version};
 use indexmap::{IndexMap, IndexSet};
+use serde::{Serialize, ser::SerializeSeq};
+
+/// The order of the versions of a duplicate, the last of which is kept when collapsing it: by version,
+/// then by source, so which of the same version from several sources is kept never depends on the
+/// order of the lock file
+pub(crate) fn keep_order(a: &Package, b: &Package) -> Ordering {
+    a.version
+        .cmp(&b.version)
+        .then_with(|| a.source.cmp(&b.source))
+}
+
+// *** TopLevels ***
+
+/// The top level packages that (directly or indirectly) depend on a version, and their dependencies it
+/// is brought in through
+#[derive(Default)]
+struct TopLevels {
+    deps: IndexSet<PkgId>,
+    packages: IndexSet<PkgId>,
+}
+
+impl TopLevels {
+    /// Finds the top levels of every version with a bit in a single pass over the dependency graph.
+    /// Walking up from a version stops at the top level packages, so the versions each package brings
+    /// in are propagated up through everything but top level packages.
+    fn build(deps: &Deps, bit_of: &[Option<usize>], bit_count: usize) -> Vec<Self> {
+        let node_count = deps.node_count();
+
+        // Only edges into packages that aren't top level are walked through
+        let below: Vec<Vec<_>> = (0..node_count)
+            .map(|id| {
+                deps.node(id)
+                    .dependencies()
+                    .iter()
+                    .copied()
+                    .filter(|&dep_id| !deps.node(dep_id).is_top_level())
+                    .collect()
+            })
+            .collect();
+
+        // Components come dependencies first, so everything below a component is already computed
+        let components = graph::strongly_connected_components(node_count, |id| &below[id]);
+        let mut component_of = vec![0; node_count];
+        let mut brought_in = vec![BitSet::default(); node_count];
+
+        for (comp_idx, component) in components.iter().enumerate() {
+            let mut bits = BitSet::new(bit_count);
+            for &id in component {
+                component_of[id] = comp_idx;
+                if let Some(bit) = bit_of[id] {
+                    bits.insert(bit);
+                }
+
+                for &dep_id in deps.node(id).dependencies() {
+                    // A top level version is walked up from, but never through
+                    if deps.node(dep_id).is_top_level() {
+                        if let Some(bit) = bit_of[dep_id] {
+                            bits.insert(bit);
+                        }
+                    } else if component_of[dep_id] != comp_idx {
+                        bits.union_with(&brought_in[dep_id]);
+                    }
+                }
+            }
+
+            for &id in component {
+                brought_in[id] = bits.clone();
+            }
+        }
+
+        let mut top_levels: Vec<Self> = (0..bit_count).map(|_| Self::default()).collect();
+        for top_level in deps.top_level_iter() {
+            for &dep_id in deps.node(top_level).dependencies() {
+                // A top level dependency only brings in itself
+                let bits: Vec<_> = if deps.node(dep_id).is_top_level() {
+                    bit_of[dep_id].into_iter().collect()
+                } else {
+                    brought_in[dep_id].iter_range(0..bit_count).collect()
+                };
+
+                for bit in bits {
+                    top_levels[bit].deps.insert(dep_id);
+                    top_levels[bit].packages.insert(top_level);
+                }
+            }
+        }
+
+        top_levels
+    }
+}
+
+// *** MultiVerDepVersion ***
+
+/// The dependents of a single version of a multi version dependency. Intermediate packages between the
+/// direct dependents and the top level dependencies are intentionally skipped.
+#[derive(Default, Serialize)]
+pub(crate) struct MultiVerDepVersion {
+    /// ID of this version in the dependency graph
+    #[serde(skip)]
+    id: PkgId,
+
+    /// Packages that directly depend on this version
+    direct_dependents: IndexSet<Package>,
+
+    /// Dependencies of top level packages that (directly or indirectly) brought in this version
+    top_level_deps: IndexSet<Package>,
+
+    /// Top level packages that (directly or indirectly) depend on this version
+    top_levels: IndexSet<Package>,
+
+    /// Packages only reachable through this version, which would leave the lock file with it
+    exclusive_packages: usize,
+}
+
+impl MultiVerDepVersion {
+    fn build(id: PkgId, deps: &Deps, exclusive_packages: usize, top_levels: TopLevels) -> Self {
+        Self {
+            id,
+            direct_dependents: Self::packages(deps, deps.node(id).dependents().iter().copied()),
+            top_level_deps: Self::packages(deps, top_levels.deps),
+            top_levels: Self::packages(deps, top_levels.packages),
+            exclusive_packages,
+        }
+    }
+
+    /// The packages of the IDs, sorted
+    fn packages(deps: &Deps, ids: impl IntoIterator<Item = PkgId>) -> IndexSet<Package> {
+        let mut pkgs: IndexSet<_> = ids.into_iter().map(|id| deps.package(id).clone()).collect();
+        pkgs.sort_unstable();
+        pkgs
+    }
+
+    fn render_pkgs<W: std::fmt::Write>(
+        w: &mut W,
+        label: &str,
+        pkgs: &IndexSet<Package>,
+    ) -> std::fmt::Result {
+        if pkgs.is_empty() {
+            return Ok(());
+        }
+
+        write!(w, "    {label}: ")?;
+        for (idx, pkg) in pkgs.iter().enumerate() {
+            write!(w, "{pkg}")?;
+            if idx < pkgs.len() - 1 {
+                write!(w, ", ")?;
+            }
+        }
+        writeln!(w)
+    }
+
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W, pkg: &Package) -> std::fmt::Result {
+        writeln!(
+            w,
+            "  {} ({} exclusive package(s))",
+            pkg.display_version(),
+            self.exclusive_packages
+        )?;
+        Self::render_pkgs(w, "Direct", &self.direct_dependents)?;
+        Self::render_pkgs(w, "Top Level Dep", &self.top_level_deps)?;
+        Self::render_pkgs(w, "Top Level", &self.top_levels)
+    }
+}
+
+// *** DuplicateKind ***
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateKind {
+    /// Different versions of the same package
+    Versions,
+    /// The same version of a package from more than one source (e.g. crates.io and a git fork)
+    Sources,
+}
+
+// *** SemverCompat ***
+
+/// The part of a version that must match for two versions to be semver compatible: the major version,
+/// or the first non-zero component for `0.x` versions (the same rule as Cargo's `^` requirements)
+fn compat_key(version: &Version) -> (u64, u64, u64) {
+    match (version.major, version.minor) {
+        (0, 0) => (0, 0, version.patch),
+        (0, minor) => (0, minor, 0),
+        (major, _) => (major, 0, 0),
+    }
+}
+
+/// True if a single `^` requirement could be satisfied by both versions
+pub fn is_semver_compatible(a: &Version, b: &Version) -> bool {
+    compat_key(a) == compat_key(b)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SemverCompat {
+    /// Every pair of versions is semver compatible (e.g. 1.2.0 and 1.4.1), so a `cargo update` or
+    /// removing a `=`/`<` pin is usually enough to fix it
+    Compatible,
+    /// Some pairs of versions are semver compatible and others are not
+    Mixed,
+    /// No two versions are semver compatible (e.g. 1.x and 2.x, or 0.3 and 0.4), so fixing it needs
+    /// upstream work
+    Incompatible,
+}
+
+impl std::fmt::Display for SemverCompat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SemverCompat::Compatible => "semver compatible",
+            SemverCompat::Mixed => "partly semver compatible",
+            SemverCompat::Incompatible => "semver incompatible",
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct VersionPair<'a> {
+    versions: [&'a Version; 2],
+    compatible: bool,
+}
 
 // *** MultiVerDep ***
 
 /// Represents a dependency that has multiple versions. It can track 3 levels of hierarchy:
 /// the direct dependent, the top level's dependencies, and the top level dependents. It intentionally
 /// skips the levels between the direct dependent and the top level dependents for brevity.
-pub(crate) struct MultiVerDep(IndexSet<Version>);
+pub(crate) struct MultiVerDep(IndexMap<Package, MultiVerDepVersion>);
 
 impl MultiVerDep {
-    pub fn new(versions: IndexSet<Version>) -> Self {
+    fn build(
+        dep: &Dep,
+        deps: &Deps,
+        exclusive: &[usize],
+        top_levels: &mut [TopLevels],
+        bit_of: &[Option<usize>],
+    ) -> Self {
+        let mut versions: IndexMap<_, _> = dep
+            .iter()
+            .map(|id| {
+                let top_levels = bit_of[id]
+                    .map(|bit| std::mem::take(&mut top_levels[bit]))
+                    .unwrap_or_default();
+                (
+                    deps.package(id).clone(),
+                    MultiVerDepVersion::build(id, deps, exclusive[id], top_levels),
+                )
+            })
+            .collect();
+
+        versions.sort_unstable_by(|a, _, b, _| keep_order(a, b));
         Self(versions)
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &Version> {
-        self.0.iter()
+    /// IDs of the versions other than the kept one (the last in `keep_order`): those that would go
+    pub fn extra_ids(&self) -> impl Iterator<Item = PkgId> + '_ {
+        self.0.values().rev().skip(1).map(|mv_ver| mv_ver.id)
+    }
+
+    /// IDs of all the versions in the dependency graph
+    pub fn ids(&self) -> impl Iterator<Item = PkgId> + '_ {
+        self.0.values().map(|mv_ver| mv_ver.id)
+    }
+
+    pub fn versions(&self) -> IndexSet<Version> {
+        self.0.keys().map(|pkg| pkg.version.clone()).collect()
+    }
+
+    /// Every version (in version order) along with its source
+    pub fn packages(&self) -> impl Iterator<Item = &Package> {
+        self.0.keys()
+    }
+
+    /// Each version (in version order) and the packages that directly depend on it
+    pub fn direct_dependents_iter(&self) -> impl Iterator<Item = (&Package, &IndexSet<Package>)> {
+        self.0
+            .iter()
+            .map(|(pkg, mv_ver)| (pkg, &mv_ver.direct_dependents))
+    }
+
+    /// `Sources` if any version is present from more than one source, otherwise `Versions`
+    pub fn kind(&self) -> DuplicateKind {
+        if self.versions().len() < self.ver_count() {
+            DuplicateKind::Sources
+        } else {
+            DuplicateKind::Versions
+        }
     }
 
     pub fn ver_count(&self) -> usize {
         self.0.len()
     }
+
+    /// Packages exclusive to the versions other than the kept one: what collapsing to it would remove
+    pub fn exclusive_count(&self) -> usize {
+        self.0
+            .values()
+            .rev()
+            .skip(1)
+            .map(|mv_ver| mv_ver.exclusive_packages)
+            .sum()
+    }
+
+    /// Every pair of distinct versions (in version order) and whether they are semver compatible
+    fn version_pairs(&self) -> Vec<VersionPair<'_>> {
+        let versions: IndexSet<_> = self.0.keys().map(|pkg| &pkg.version).collect();
+
+        versions
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, &a)| {
+                versions[idx + 1..].iter().map(move |&b| VersionPair {
+                    versions: [a, b],
+                    compatible: is_semver_compatible(a, b),
+                })
+            })
+            .collect()
+    }
+
+    /// Classifies the pairs of distinct versions (`None` if the duplicate only differs by source)
+    pub fn semver_compat(&self) -> Option<SemverCompat> {
+        let pairs = self.version_pairs();
+
+        if pairs.is_empty() {
+            None
+        } else if pairs.iter().all(|pair| pair.compatible) {
+            Some(SemverCompat::Compatible)
+        } else if pairs.iter().any(|pair| pair.compatible) {
+            Some(SemverCompat::Mixed)
+        } else {
+            Some(SemverCompat::Incompatible)
+        }
+    }
+
+    /// Groups of distinct versions that are semver compatible with each other
+    fn compatible_groups(&self) -> Vec<Vec<&Version>> {
+        let mut groups: IndexMap<_, Vec<_>> = IndexMap::new();
+        for pkg in self.0.keys() {
+            let group = groups.entry(compat_key(&pkg.version)).or_default();
+            if !group.contains(&&pkg.version) {
+                group.push(&pkg.version);
+            }
+        }
+
+        groups.into_values().collect()
+    }
+
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W, name: &Name) -> std::fmt::Result {
+        write!(w, "{name} ({self})")?;
+        match self.semver_compat() {
+            // Only partly compatible duplicates need to say which versions can be merged
+            Some(SemverCompat::Mixed) => {
+                let groups = self
+                    .compatible_groups()
+                    .iter()
+                    .filter(|group| group.len() > 1)
+                    .map(|group| {
+                        group
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                writeln!(w, " [{}: {groups}]", SemverCompat::Mixed)?;
+            }
+            Some(compat) => writeln!(w, " [{compat}]")?,
+            None => writeln!(w)?,
+        }
+
+        for (pkg, mv_ver) in &self.0 {
+            mv_ver.render(w, pkg)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl std::fmt::Display for MultiVerDep {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let versions = self
             .0
-            .iter()
-            .map(ToString::to_string)
+            .keys()
+            .map(Package::display_version)
             .collect::<Vec<_>>()
             .join(", ");
         f.write_str(&versions)
     }
 }
 
+impl Serialize for MultiVerDep {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            version: &'a Version,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            source: &'a Option<SourceId>,
+            #[serde(flatten)]
+            dependents: &'a MultiVerDepVersion,
+        }
+
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for (pkg, dependents) in &self.0 {
+            seq.serialize_element(&Entry {
+                version: &pkg.version,
+                source: &pkg.source,
+                dependents,
+            })?;
+        }
+        seq.end()
+    }
+}
+
 // *** MultiVerDeps ***
 
-pub struct MultiVerDeps(IndexMap<Name, MultiVerDep>);
+pub struct MultiVerDeps {
+    deps: IndexMap<Name, MultiVerDep>,
+
+    /// Multi version dependencies tolerated by the config. These take no part in blame or the exit code
+    allowed: IndexMap<Name, MultiVerDep>,
+}
 
 impl MultiVerDeps {
-    pub fn from_deps(deps: &Deps) -> Self {
-        let mut multi_ver_deps: IndexMap<_, _> = deps
+    pub fn from_deps(deps: &Deps, config: &Config) -> Result<Self, String> {
+        let mut multi_ver_deps = IndexMap::new();
+        let mut allowed = IndexMap::new();
+
+        // The number of packages only reachable through each package
+        let exclusive = graph::dominated_counts(deps.node_count(), deps.top_level_iter(), |id| {
+            deps.node(id).dependencies()
+        });
+
+        let dups: Vec<_> = deps
             .iter()
-            .filter_map(|(name, dep)| {
-                if dep.has_multiple_versions() {
-                    Some((name.clone(), MultiVerDep::new(dep.versions())))
-                } else {
-                    None
-                }
-            })
+            .filter(|(_, dep)| dep.has_multiple_versions())
             .collect();
+        let mut bit_of = vec![None; deps.node_count()];
+        let mut bit_count = 0;
+        for id in dups.iter().flat_map(|(_, dep)| dep.iter()) {
+            bit_of[id] = Some(bit_count);
+            bit_count += 1;
+        }
+        let mut top_levels = TopLevels::build(deps, &bit_of, bit_count);
+
+        for (name, dep) in dups {
+            let versions = dep.versions();
+            let mv_dep = MultiVerDep::build(dep, deps, &exclusive, &mut top_levels, &bit_of);
+
+            if config.is_allowed(name, &versions) {
+                allowed.insert(name.clone(), mv_dep);
+            } else {
+                multi_ver_deps.insert(name.clone(), mv_dep);
+            }
+        }
 
         multi_ver_deps.sort_unstable_keys();
-        Self(multi_ver_deps)
+        allowed.sort_unstable_keys();
+        Ok(Self {
+            deps: multi_ver_deps,
+            allowed,
+        })
     }
 
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.deps.is_empty()
     }
 
     pub fn dup_pkg_count(&self) -> usize {
-        self.0.len()
+        self.deps.len()
     }
 
     pub fn dup_ver_count(&self) -> usize {
-        self.0.values().map(|mv_dep| mv_dep.ver_count()).sum()
+        self.deps.values().map(|mv_dep| mv_dep.ver_count()).sum()
+    }
+
+    pub fn allowed_count(&self) -> usize {
+        self.allowed.len()
     }
 
     pub(crate) fn iter(&self) -> impl Iterator<Item = (&Name, &MultiVerDep)> {
-        self.0.iter()
+        self.deps.iter()
     }
-}
 
-impl std::fmt::Display for MultiVerDeps {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for (name, multi_ver_dep) in &self.0 {
-            writeln!(f, "{name} ({multi_ver_dep})")?;
+    pub(crate) fn get(&self, name: &Name) -> Option<&MultiVerDep> {
+        self.deps.get(name)
+    }
+
+    /// Names and versions of all multi version dependencies that were allowed by the config
+    pub fn allowed_iter(&self) -> impl Iterator<Item = (&Name, IndexSet<Version>)> {
+        self.allowed
+            .iter()
+            .map(|(name, mv_dep)| (name, mv_dep.versions()))
+    }
+
+    /// The number of multi version dependencies with at least one pair of semver compatible versions
+    pub fn semver_compatible_count(&self) -> usize {
+        self.deps
+            .values()
+            .filter(|mv_dep| {
+                matches!(
+                    mv_dep.semver_compat(),
+                    Some(SemverCompat::Compatible | SemverCompat::Mixed)
+                )
+            })
+            .count()
+    }
+
+    /// The number of multi version dependencies of the given kind
+    pub fn kind_count(&self, kind: DuplicateKind) -> usize {
+        self.deps
+            .values()
+            .filter(|mv_dep| mv_dep.kind() == kind)
+            .count()
+    }
+
+    /// Renders the multi version dependencies of the given kind, those removing the most packages when
+    /// collapsed first
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W, kind: DuplicateKind) -> std::fmt::Result {
+        let mut multi_ver_deps: Vec<_> = self
+            .deps
+            .iter()
+            .filter(|(_, multi_ver_dep)| multi_ver_dep.kind() == kind)
+            .collect();
+        multi_ver_deps.sort_by_key(|(_, multi_ver_dep)| Reverse(multi_ver_dep.exclusive_count()));
+
+        for (name, multi_ver_dep) in multi_ver_deps {
+            multi_ver_dep.render(w, name)?;
         }
 
         Ok(())
     }
+
+    pub fn render_allowed<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        for (name, multi_ver_dep) in &self.allowed {
+            writeln!(w, "{name} ({multi_ver_dep})")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MultiVerDeps {
+    pub(crate) fn serialize_deps<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serialize_multi_ver_deps(&self.deps, serializer)
+    }
+
+    pub(crate) fn serialize_allowed<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serialize_multi_ver_deps(&self.allowed, serializer)
+    }
+}
+
+fn serialize_multi_ver_deps<S: serde::Serializer>(
+    multi_ver_deps: &IndexMap<Name, MultiVerDep>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        name: &'a Name,
+        kind: DuplicateKind,
+        semver: Option<SemverCompat>,
+        version_pairs: Vec<VersionPair<'a>>,
+        versions: &'a MultiVerDep,
+    }
+
+    let mut seq = serializer.serialize_seq(Some(multi_ver_deps.len()))?;
+    for (name, versions) in multi_ver_deps {
+        seq.serialize_element(&Entry {
+            name,
+            kind: versions.kind(),
+            semver: versions.semver_compat(),
+            version_pairs: versions.version_pairs(),
+            versions,
+        })?;
+    }
+    seq.end()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::random_locks::random_locks;
+
+    use cargo_lock::Lockfile;
+
+    const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+    const GIT: &str = "git+https://github.com/someone/dup#0123456789abcdef0123456789abcdef01234567";
+
+    fn multi_ver_deps(lock: &str) -> (Deps, MultiVerDeps) {
+        let deps = Deps::from_lock_file(Lockfile::from_str(lock).unwrap()).unwrap();
+        let multi_ver_deps = MultiVerDeps::from_deps(&deps, &Config::default()).unwrap();
+        (deps, multi_ver_deps)
+    }
+
+    /// The original algorithm: walk up the dependents of the version, stopping at top level packages
+    fn per_version_walk(deps: &Deps, id: PkgId) -> (IndexSet<Package>, IndexSet<Package>) {
+        let mut top_level_deps = IndexSet::new();
+        let mut top_levels = IndexSet::new();
+        let mut visited = vec![false; deps.node_count()];
+        let mut work_queue = vec![id];
+
+        while let Some(curr_id) = work_queue.pop() {
+            if std::mem::replace(&mut visited[curr_id], true) {
+                continue;
+            }
+
+            for &dependent in deps.node(curr_id).dependents() {
+                if deps.node(dependent).is_top_level() {
+                    top_level_deps.insert(deps.package(curr_id).clone());
+                    top_levels.insert(deps.package(dependent).clone());
+                } else {
+                    work_queue.push(dependent);
+                }
+            }
+        }
+
+        top_level_deps.sort_unstable();
+        top_levels.sort_unstable();
+        (top_level_deps, top_levels)
+    }
+
+    fn assert_same_as_per_version_walk(lock: &str, context: &str) {
+        let (deps, multi_ver_deps) = multi_ver_deps(lock);
+
+        for (_, mv_dep) in multi_ver_deps.iter() {
+            for (pkg, mv_ver) in &mv_dep.0 {
+                let (top_level_deps, top_levels) = per_version_walk(&deps, mv_ver.id);
+                assert_eq!(mv_ver.top_level_deps, top_level_deps, "{context}: {pkg}");
+                assert_eq!(mv_ver.top_levels, top_levels, "{context}: {pkg}");
+            }
+        }
+    }
+
+    #[test]
+    fn same_top_levels_as_per_version_walk_on_fixtures() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "lock") {
+                let lock = std::fs::read_to_string(&path).unwrap();
+                assert_same_as_per_version_walk(&lock, &path.display().to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn same_top_levels_as_per_version_walk_on_random_graphs() {
+        // Local packages are top level, and cycles go through them too
+        for (graph, lock) in random_locks(11, 20).iter().enumerate() {
+            assert_same_as_per_version_walk(lock, &format!("random graph {graph}"));
+        }
+    }
+
+    #[test]
+    fn compat_key_is_the_first_non_zero_component() {
+        let key = |version| compat_key(&Version::parse(version).unwrap());
+
+        assert_eq!(key("1.2.3"), (1, 0, 0));
+        assert_eq!(key("2.0.0"), (2, 0, 0));
+        assert_eq!(key("0.3.7"), (0, 3, 0));
+        assert_eq!(key("0.0.2"), (0, 0, 2));
+        assert_eq!(key("1.2.0-beta.1"), key("1.4.1"));
+    }
+
+    /// `app` depends on `dup 1.0.0` from crates.io and git, and only the crates.io one brings in `extra`
+    fn source_only_lock(git_first: bool) -> String {
+        let mut dups = [
+            format!(
+                "[[package]]\nname = \"dup\"\nversion = \"1.0.0\"\nsource = \"{CRATES_IO}\"\ndependencies = [\"extra\"]\n"
+            ),
+            format!("[[package]]\nname = \"dup\"\nversion = \"1.0.0\"\nsource = \"{GIT}\"\n"),
+        ];
+        if git_first {
+            dups.reverse();
+        }
+
+        format!(
+            "version = 4\n\n[[package]]\nname = \"app\"\nversion = \"0.1.0\"\ndependencies = [\n \"dup 1.0.0 ({CRATES_IO})\",\n \"dup 1.0.0 ({GIT})\",\n]\n\n{}\n{}\n[[package]]\nname = \"extra\"\nversion = \"1.0.0\"\nsource = \"{CRATES_IO}\"\n",
+            dups[0], dups[1]
+        )
+    }
+
+    #[test]
+    fn source_only_duplicate_keeps_the_same_version_in_any_order() {
+        for git_first in [false, true] {
+            let (_, multi_ver_deps) = multi_ver_deps(&source_only_lock(git_first));
+            let dup = multi_ver_deps.get(&Name::from_str("dup").unwrap()).unwrap();
+            let (kept, _) = dup.0.last().unwrap();
+
+            // Of the same version, the greater source URL is kept, so the crates.io one has to go
+            assert_eq!(kept.source.as_ref().unwrap().to_string(), GIT);
+            assert_eq!(dup.exclusive_count(), 1, "git first: {git_first}");
+        }
+    }
 }
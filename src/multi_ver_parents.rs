@@ -1,86 +1,254 @@
-use crate::{dep_tree::Deps, Package};
+use std::collections::VecDeque;
+use std::ops::Range;
 
-use cargo_lock::{Name, Version};
+use crate::Package;
+use crate::dep_tree::{Deps, PkgId};
+use crate::graph::{self, BitSet};
+
+use cargo_lock::Name;
 use indexmap::{IndexMap, IndexSet};
 
-// *** MultiVerDeps ***
+// *** MultiVerParents ***
 
+/// For every package, the multi version dependency versions it (directly or indirectly) depends on.
+/// Each multi version dependency version is assigned a bit, and the bits of all the versions of a given
+/// multi version dependency are contiguous so they can be compared as a range.
 #[derive(Default)]
-pub(crate) struct MultiVerDeps {
-    deps: IndexMap<Name, IndexSet<Version>>,
-}
-
-impl MultiVerDeps {
-    fn add(&mut self, name: Name, version: Version) {
-        self.deps.entry(name).or_default().insert(version);
-    }
-
-    pub fn get(&self, name: &Name) -> Option<&IndexSet<Version>> {
-        self.deps.get(name)
-    }
+pub struct MultiVerDepParents {
+    /// The package ID of the multi version dependency version of each bit
+    bits: Vec<PkgId>,
 
-    pub fn multi_ver_iter(&self) -> impl Iterator<Item = (&Name, &IndexSet<Version>)> {
-        self.deps.iter().filter(|(_, versions)| versions.len() > 1)
-    }
-}
+    /// The range of bits of each multi version dependency
+    names: IndexMap<Name, Range<usize>>,
 
-// *** MultiVerParents ***
+    /// Bits of the multi version dependency versions reachable below each package (indexed by ID)
+    reachable: Vec<BitSet>,
 
-#[derive(Default)]
-pub struct MultiVerDepParents {
-    parents: IndexMap<Package, MultiVerDeps>,
+    /// Dependency cycles above multi version dependencies (each in dependency order)
+    cycles: IndexSet<Vec<Package>>,
 }
 
 impl MultiVerDepParents {
+    /// Propagates the multi version dependency versions reachable from each package in a single pass
+    /// over the dependency graph in reverse topological order. Each package's set is a bitset of all
+    /// multi version dependency versions reachable below it, so every package is only processed once.
     pub fn build(
         deps: &Deps,
         multi_ver_deps: &crate::multi_ver_deps::MultiVerDeps,
     ) -> Result<Self, String> {
-        let mut multi_ver_parents = Self {
-            parents: IndexMap::new(),
-        };
+        let node_count = deps.node_count();
+        let mut multi_ver_parents = Self::default();
+        let mut bit_of = vec![None; node_count];
 
         for (name, mv_dep) in multi_ver_deps.iter() {
-            for version in mv_dep.iter() {
-                let pkg = Package {
-                    name: name.clone(),
-                    version: version.clone(),
-                };
-                multi_ver_parents.build_multi_ver_parents(&pkg, deps)?;
+            let start = multi_ver_parents.bits.len();
+            for id in mv_dep.ids() {
+                bit_of[id] = Some(multi_ver_parents.bits.len());
+                multi_ver_parents.bits.push(id);
             }
+            multi_ver_parents
+                .names
+                .insert(name.clone(), start..multi_ver_parents.bits.len());
         }
 
+        // Components come dependencies first, so everything below a component is already computed
+        let components =
+            graph::strongly_connected_components(node_count, |id| deps.node(id).dependencies());
+        let mut component_of = vec![0; node_count];
+        let bit_count = multi_ver_parents.bits.len();
+        let reachable = &mut multi_ver_parents.reachable;
+        reachable.resize(node_count, BitSet::new(bit_count));
+
+        for (comp_idx, component) in components.iter().enumerate() {
+            let mut below = BitSet::new(bit_count);
+
+            for &id in component {
+                component_of[id] = comp_idx;
+
+                for &dep_id in deps.node(id).dependencies() {
+                    below.union_with(&reachable[dep_id]);
+                    if let Some(bit) = bit_of[dep_id] {
+                        below.insert(bit);
+                    }
+                }
+            }
+
+            // A package is never its own parent, even when it is part of a cycle
+            for &id in component {
+                let mut id_reachable = below.clone();
+                if let Some(bit) = bit_of[id] {
+                    id_reachable.remove(bit);
+                }
+                reachable[id] = id_reachable;
+            }
+
+            // A single package is only a cycle if it depends on itself
+            let is_cycle = match component.as_slice() {
+                [id] => deps.node(*id).dependencies().contains(id),
+                _ => true,
+            };
+            if is_cycle && !below.is_empty() {
+                let cycle = Self::find_cycle(deps, component, &component_of, comp_idx);
+                multi_ver_parents.cycles.insert(cycle);
+            }
+        }
+
+        multi_ver_parents.cycles.sort_unstable();
         Ok(multi_ver_parents)
     }
 
-    fn build_multi_ver_parents(&mut self, pkg: &Package, deps: &Deps) -> Result<(), String> {
-        fn next(
-            pkg: &Package,
-            curr_pkg: &Package,
-            deps: &Deps,
-            parents: &mut MultiVerDepParents,
-        ) -> Result<(), String> {
-            let ver = deps.get_version(curr_pkg)?;
-
-            if pkg != curr_pkg {
-                parents.add(curr_pkg.clone(), pkg.name.clone(), pkg.version.clone());
+    /// Returns the shortest cycle (in dependency order) through the smallest package of the component
+    fn find_cycle(
+        deps: &Deps,
+        component: &[PkgId],
+        component_of: &[usize],
+        comp_idx: usize,
+    ) -> Vec<Package> {
+        let start = component
+            .iter()
+            .copied()
+            .min_by_key(|&id| deps.package(id))
+            .expect("strongly connected components are never empty");
+
+        // Breadth first search within the component back to the start
+        let mut prev = IndexMap::new();
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(id) = queue.pop_front() {
+            for &dep_id in deps.node(id).dependencies() {
+                if dep_id == start {
+                    let mut cycle = vec![deps.package(id).clone()];
+                    let mut curr = id;
+                    while curr != start {
+                        curr = prev[&curr];
+                        cycle.push(deps.package(curr).clone());
+                    }
+                    cycle.reverse();
+                    return cycle;
+                }
+
+                if component_of[dep_id] == comp_idx && !prev.contains_key(&dep_id) {
+                    prev.insert(dep_id, id);
+                    queue.push_back(dep_id);
+                }
             }
+        }
 
-            for dependent in ver.dependents() {
-                next(pkg, dependent, deps, parents)?;
-            }
+        // Unreachable for a strongly connected component that is a cycle
+        Vec::new()
+    }
+
+    pub(crate) fn cycles(&self) -> impl Iterator<Item = &Vec<Package>> {
+        self.cycles.iter()
+    }
+
+    /// Multi version dependency names and their range of bits
+    pub(crate) fn names(&self) -> impl Iterator<Item = (&Name, Range<usize>)> {
+        self.names.iter().map(|(name, range)| (name, range.clone()))
+    }
+
+    /// The package ID of the multi version dependency version of the bit
+    pub(crate) fn bit_id(&self, bit: usize) -> PkgId {
+        self.bits[bit]
+    }
+
+    /// Bits of the multi version dependency versions the package depends on (if parents were built)
+    pub(crate) fn get_multi_ver_deps(&self, parent: PkgId) -> Option<&BitSet> {
+        self.reachable.get(parent)
+    }
+
+    /// Bits in the range of the versions that depending on the package brings in. If it is one of the
+    /// versions, that is just its own bit (depending on it picks that version), otherwise it is the
+    /// versions it depends on.
+    pub(crate) fn carried(&self, id: PkgId, bits: Range<usize>) -> Vec<usize> {
+        match bits.clone().find(|&bit| self.bits[bit] == id) {
+            Some(bit) => vec![bit],
+            None => match self.reachable.get(id) {
+                Some(reachable) => reachable.iter_range(bits).collect(),
+                None => Vec::new(),
+            },
+        }
+    }
+}
 
-            Ok(())
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::Config;
+    use crate::multi_ver_deps::MultiVerDeps;
+    use crate::random_locks::random_locks;
+
+    use cargo_lock::Lockfile;
+
+    /// The original algorithm: walk up the dependents of every multi version dependency version
+    /// separately, recording the version in every package on the way (except the version itself)
+    fn per_path_walk(deps: &Deps, multi_ver_deps: &MultiVerDeps) -> Vec<IndexSet<PkgId>> {
+        let mut parents = vec![IndexSet::new(); deps.node_count()];
+
+        for (_, mv_dep) in multi_ver_deps.iter() {
+            for version in mv_dep.ids() {
+                let mut visited = vec![false; deps.node_count()];
+                let mut work_queue = vec![version];
+
+                while let Some(id) = work_queue.pop() {
+                    if std::mem::replace(&mut visited[id], true) {
+                        continue;
+                    }
+                    if id != version {
+                        parents[id].insert(version);
+                    }
+                    work_queue.extend(deps.node(id).dependents());
+                }
+            }
         }
 
-        next(pkg, pkg, deps, self)
+        parents
     }
 
-    fn add(&mut self, parent: Package, name: Name, ver: Version) {
-        self.parents.entry(parent).or_default().add(name, ver);
+    fn assert_same_as_per_path_walk(lock: &str, context: &str) {
+        let deps = Deps::from_lock_file(Lockfile::from_str(lock).unwrap()).unwrap();
+        let multi_ver_deps = MultiVerDeps::from_deps(&deps, &Config::default()).unwrap();
+        let parents = MultiVerDepParents::build(&deps, &multi_ver_deps).unwrap();
+        let expected = per_path_walk(&deps, &multi_ver_deps);
+
+        for (id, expected) in expected.iter().enumerate() {
+            let mut actual: IndexSet<_> = parents
+                .get_multi_ver_deps(id)
+                .unwrap()
+                .iter_range(0..parents.bits.len())
+                .map(|bit| parents.bit_id(bit))
+                .collect();
+            let mut expected = expected.clone();
+            actual.sort_unstable();
+            expected.sort_unstable();
+
+            assert_eq!(actual, expected, "{context}: {}", deps.package(id));
+        }
     }
 
-    pub(crate) fn get_multi_ver_deps(&self, parent: &Package) -> Option<&MultiVerDeps> {
-        self.parents.get(parent)
+    #[test]
+    fn same_as_per_path_walk_on_fixtures() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+        let mut count = 0;
+
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "lock") {
+                let lock = std::fs::read_to_string(&path).unwrap();
+                assert_same_as_per_path_walk(&lock, &path.display().to_string());
+                count += 1;
+            }
+        }
+
+        assert!(count >= 8, "only {count} fixtures found in {dir}");
+    }
+
+    #[test]
+    fn same_as_per_path_walk_on_random_graphs() {
+        for (graph, lock) in random_locks(7, 20).iter().enumerate() {
+            assert_same_as_per_path_walk(lock, &format!("random graph {graph}"));
+        }
     }
 }
@@ -0,0 +1,610 @@
+//! Finds a small set of package changes that would collapse every multi version dependency to a single
+//! version. A change either bumps a package (cutting its dependencies on older duplicate versions) or
+//! drops a dependency of a top level package (cutting everything only reachable through it).
+//!
+//! Every direct dependent of an older version has to be hit by some change before the version is
+//! gone, which makes this a hitting set problem. Solving it exactly is NP-hard, so changes are chosen
+//! greedily by how many duplicates they remove, then pruned of any change later ones made redundant.
+//! Finally they are listed by payoff, with changes that only remove a duplicate together as one step.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::dep_tree::{Deps, PkgId};
+use crate::multi_ver_deps::keep_order;
+use crate::{MultiVerDepParents, Package};
+
+use cargo_lock::Name;
+use indexmap::IndexSet;
+use serde::Serialize;
+
+/// A change as cut edges of the dependency graph
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Change {
+    /// Cut all dependencies of the package on older duplicate versions
+    Bump(PkgId),
+    /// Cut the dependency of a top level package
+    Drop(PkgId, PkgId),
+}
+
+/// How much a change accomplishes. Compared in field order, so removing duplicates always wins.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+struct Score {
+    /// Duplicates collapsed to a single version
+    collapsed: usize,
+    /// Older duplicate versions no longer reachable
+    versions: usize,
+    /// Dependencies on older duplicate versions cut
+    edges: usize,
+    /// Bumping is less disruptive than dropping a dependency
+    is_bump: bool,
+}
+
+// *** PlanGraph ***
+
+/// The dependency graph with the edges cut by the changes chosen so far
+struct PlanGraph<'a> {
+    deps: &'a Deps,
+    roots: Vec<PkgId>,
+
+    /// Versions of each multi version dependency
+    dups: Vec<(&'a Name, Vec<PkgId>)>,
+    /// The multi version dependency each package is a version of (indexed by ID)
+    dup_of: Vec<Option<usize>>,
+
+    cut: HashSet<(PkgId, PkgId)>,
+    /// Packages still reachable from the roots (indexed by ID)
+    alive: Vec<bool>,
+    /// Number of live edges into each package (indexed by ID)
+    live_in: Vec<usize>,
+    /// The version of each multi version dependency that is kept: the last one still reachable in
+    /// `keep_order`
+    kept: Vec<Option<PkgId>>,
+}
+
+impl<'a> PlanGraph<'a> {
+    fn new(deps: &'a Deps, parents: &'a MultiVerDepParents) -> Self {
+        let mut dup_of = vec![None; deps.node_count()];
+        let dups: Vec<_> = parents
+            .names()
+            .enumerate()
+            .map(|(idx, (name, bits))| {
+                let ids: Vec<_> = bits.map(|bit| parents.bit_id(bit)).collect();
+                for &id in &ids {
+                    dup_of[id] = Some(idx);
+                }
+                (name, ids)
+            })
+            .collect();
+
+        let mut graph = Self {
+            deps,
+            roots: deps.top_level_iter().collect(),
+            kept: vec![None; dups.len()],
+            dups,
+            dup_of,
+            cut: HashSet::new(),
+            alive: Vec::new(),
+            live_in: Vec::new(),
+        };
+        graph.update();
+        graph
+    }
+
+    /// The graph after all the changes (cutting the edges of each as they are at the start, which is
+    /// much faster than applying them one by one)
+    fn with_changes(deps: &'a Deps, parents: &'a MultiVerDepParents, changes: &[Change]) -> Self {
+        let mut graph = Self::new(deps, parents);
+        let cut: Vec<_> = changes
+            .iter()
+            .flat_map(|&change| graph.cut_edges(change))
+            .collect();
+        graph.cut.extend(cut);
+        graph.update();
+        graph
+    }
+
+    fn is_live_edge(&self, from: PkgId, to: PkgId) -> bool {
+        self.alive[from] && !self.cut.contains(&(from, to))
+    }
+
+    /// True if the package is a reachable version of a multi version dependency that isn't kept
+    fn is_old(&self, id: PkgId) -> bool {
+        self.alive[id] && self.dup_of[id].is_some_and(|dup_idx| self.kept[dup_idx] != Some(id))
+    }
+
+    fn live_count(&self, dup_idx: usize) -> usize {
+        self.dups[dup_idx]
+            .1
+            .iter()
+            .filter(|&&id| self.alive[id])
+            .count()
+    }
+
+    fn is_collapsed(&self, dup_idx: usize) -> bool {
+        self.live_count(dup_idx) <= 1
+    }
+
+    fn collapsed_count(&self) -> usize {
+        (0..self.dups.len())
+            .filter(|&dup_idx| self.is_collapsed(dup_idx))
+            .count()
+    }
+
+    fn is_done(&self) -> bool {
+        self.collapsed_count() == self.dups.len()
+    }
+
+    /// Recomputes what is reachable after cutting edges (exactly, unlike `trial`)
+    fn update(&mut self) {
+        let node_count = self.deps.node_count();
+        let mut alive = vec![false; node_count];
+        let mut work_queue = self.roots.clone();
+
+        while let Some(id) = work_queue.pop() {
+            if !std::mem::replace(&mut alive[id], true) {
+                work_queue.extend(
+                    self.deps
+                        .node(id)
+                        .dependencies()
+                        .iter()
+                        .filter(|&&dep_id| !self.cut.contains(&(id, dep_id))),
+                );
+            }
+        }
+        self.alive = alive;
+
+        self.live_in = vec![0; node_count];
+        for id in 0..node_count {
+            for &dep_id in self.deps.node(id).dependencies() {
+                if self.is_live_edge(id, dep_id) {
+                    self.live_in[dep_id] += 1;
+                }
+            }
+        }
+
+        self.kept = self
+            .dups
+            .iter()
+            .map(|(_, ids)| {
+                ids.iter()
+                    .copied()
+                    .filter(|&id| self.alive[id])
+                    .max_by(|&a, &b| keep_order(self.deps.package(a), self.deps.package(b)))
+            })
+            .collect();
+    }
+
+    /// The live edges the change cuts directly
+    fn cut_edges(&self, change: Change) -> Vec<(PkgId, PkgId)> {
+        match change {
+            Change::Bump(id) => self
+                .deps
+                .node(id)
+                .dependencies()
+                .iter()
+                .filter(|&&dep_id| self.is_live_edge(id, dep_id) && self.is_old(dep_id))
+                .map(|&dep_id| (id, dep_id))
+                .collect(),
+            Change::Drop(id, dep_id) if self.is_live_edge(id, dep_id) => vec![(id, dep_id)],
+            Change::Drop(..) => Vec::new(),
+        }
+    }
+
+    /// Estimates what the change accomplishes by counting down live incoming edges, and returns the
+    /// packages that become unreachable. Cycles that become unreachable are missed, so this never
+    /// overestimates.
+    fn trial(&self, change: Change) -> (Score, Vec<PkgId>) {
+        let mut score = Score {
+            is_bump: matches!(change, Change::Bump(_)),
+            ..Default::default()
+        };
+        let mut removed: HashMap<PkgId, usize> = HashMap::new();
+        let mut dead = Vec::new();
+        let mut work_queue = self.cut_edges(change);
+
+        while let Some((_, to)) = work_queue.pop() {
+            if self.is_old(to) {
+                score.edges += 1;
+            }
+
+            let removed = removed.entry(to).or_default();
+            *removed += 1;
+            if *removed == self.live_in[to] && !self.deps.node(to).is_top_level() {
+                dead.push(to);
+                work_queue.extend(
+                    self.deps
+                        .node(to)
+                        .dependencies()
+                        .iter()
+                        .filter(|&&dep_id| self.is_live_edge(to, dep_id))
+                        .map(|&dep_id| (to, dep_id)),
+                );
+            }
+        }
+
+        let mut dead_per_dup: HashMap<usize, usize> = HashMap::new();
+        for &id in &dead {
+            if let Some(dup_idx) = self.dup_of[id] {
+                *dead_per_dup.entry(dup_idx).or_default() += 1;
+                if self.is_old(id) {
+                    score.versions += 1;
+                }
+            }
+        }
+        score.collapsed = dead_per_dup
+            .into_iter()
+            .filter(|&(dup_idx, count)| {
+                !self.is_collapsed(dup_idx) && self.live_count(dup_idx) - count <= 1
+            })
+            .count();
+
+        (score, dead)
+    }
+
+    /// Names of the multi version dependencies whose older versions lose a dependent to the change
+    fn helped_by(&self, change: Change) -> IndexSet<Name> {
+        let (_, dead) = self.trial(change);
+        let cut = self.cut_edges(change);
+        let dead_edges = dead.iter().flat_map(|&id| {
+            self.deps
+                .node(id)
+                .dependencies()
+                .iter()
+                .map(move |&dep_id| (id, dep_id))
+        });
+
+        cut.iter()
+            .copied()
+            .chain(dead_edges)
+            .filter(|&(from, to)| self.is_live_edge(from, to) && self.is_old(to))
+            .filter_map(|(_, to)| self.dup_of[to])
+            .chain(dead.iter().filter_map(|&id| self.dup_of[id]))
+            .map(|dup_idx| self.dups[dup_idx].0.clone())
+            .collect()
+    }
+
+    /// Packages that depend on an older version, and dependencies of top level packages with an older
+    /// version at or below them
+    fn candidates(&self, parents: &MultiVerDepParents) -> Vec<Change> {
+        let mut bumps = IndexSet::new();
+        for (dup_idx, (_, ids)) in self.dups.iter().enumerate() {
+            if self.is_collapsed(dup_idx) {
+                continue;
+            }
+
+            for &id in ids.iter().filter(|&&id| self.is_old(id)) {
+                for &dependent in self.deps.node(id).dependents() {
+                    if self.is_live_edge(dependent, id) {
+                        bumps.insert(dependent);
+                    }
+                }
+            }
+        }
+
+        let has_old_below = |id: PkgId| {
+            self.is_old(id)
+                || parents.get_multi_ver_deps(id).is_some_and(|bits| {
+                    parents
+                        .names()
+                        .flat_map(|(_, bits_range)| bits.iter_range(bits_range))
+                        .any(|bit| self.is_old(parents.bit_id(bit)))
+                })
+        };
+        let drops = self.roots.iter().flat_map(|&root| {
+            self.deps
+                .node(root)
+                .dependencies()
+                .iter()
+                .filter(move |&&dep_id| {
+                    self.is_live_edge(root, dep_id)
+                        && !self.deps.node(dep_id).is_top_level()
+                        && has_old_below(dep_id)
+                })
+                .map(move |&dep_id| Change::Drop(root, dep_id))
+        });
+
+        bumps.into_iter().map(Change::Bump).chain(drops).collect()
+    }
+
+    fn apply(&mut self, change: Change) {
+        let edges = self.cut_edges(change);
+        self.cut.extend(edges);
+        self.update();
+    }
+
+    /// The change as displayed
+    fn plan_change(&self, change: Change) -> PlanChange {
+        let (action, package, dependent) = match change {
+            Change::Bump(id) => (PlanAction::Bump, id, None),
+            Change::Drop(id, dep_id) => (PlanAction::Drop, dep_id, Some(id)),
+        };
+
+        PlanChange {
+            action,
+            package: self.deps.package(package).clone(),
+            dependent: dependent.map(|id| self.deps.package(id).clone()),
+        }
+    }
+}
+
+// *** PlanAction ***
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PlanAction {
+    /// Upgrade the package (or for a local package, its requirements) to the kept versions
+    Bump,
+    /// Remove the package from the dependencies of a top level package
+    Drop,
+}
+
+// *** PlanChange ***
+
+#[derive(Serialize)]
+pub(crate) struct PlanChange {
+    action: PlanAction,
+    package: Package,
+    /// The top level package the package is dropped from (only for drops)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dependent: Option<Package>,
+}
+
+impl std::fmt::Display for PlanChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.action, &self.dependent) {
+            (PlanAction::Drop, Some(dependent)) => {
+                write!(f, "drop {} from {dependent}", self.package)
+            }
+            _ if self.package.source.is_none() => {
+                write!(f, "bump the requirements of {}", self.package)
+            }
+            _ => write!(f, "bump {}", self.package),
+        }
+    }
+}
+
+// *** PlanStep ***
+
+#[derive(Serialize)]
+pub(crate) struct PlanStep {
+    /// The changes to make together (more than one when none of them removes a duplicate alone)
+    changes: Vec<PlanChange>,
+    /// Multi version dependencies this step collapses to a single version (given the earlier steps)
+    removes: IndexSet<Name>,
+    /// Multi version dependencies this step gets closer to a single version without finishing them
+    helps: IndexSet<Name>,
+}
+
+impl PlanStep {
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W, number: usize) -> std::fmt::Result {
+        let changes = self
+            .changes
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" and ");
+        write!(w, "{number}. {changes}")?;
+
+        let join = |names: &IndexSet<Name>| {
+            names
+                .iter()
+                .map(Name::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match (self.removes.is_empty(), self.helps.is_empty()) {
+            (false, true) => writeln!(
+                w,
+                " (removes {} duplicate(s): {})",
+                self.removes.len(),
+                join(&self.removes)
+            ),
+            (false, false) => writeln!(
+                w,
+                " (removes {} duplicate(s): {}; helps remove: {})",
+                self.removes.len(),
+                join(&self.removes),
+                join(&self.helps)
+            ),
+            (true, _) => writeln!(w, " (helps remove: {})", join(&self.helps)),
+        }
+    }
+}
+
+// *** UpgradePlan ***
+
+#[derive(Default, Serialize)]
+pub struct UpgradePlan(Vec<PlanStep>);
+
+impl UpgradePlan {
+    pub fn build(deps: &Deps, parents: &MultiVerDepParents) -> Self {
+        let mut graph = PlanGraph::new(deps, parents);
+        let mut changes = Vec::new();
+
+        // Greedy: take the change that accomplishes the most until every duplicate is collapsed. A
+        // dependent of an older version can always be bumped, so there is progress unless a top level
+        // package itself is an older version.
+        while !graph.is_done() {
+            let best = graph
+                .candidates(parents)
+                .into_iter()
+                .map(|change| (graph.trial(change).0, change))
+                .filter(|(score, _)| score.edges > 0 || score.collapsed > 0)
+                // Keep the first of equal scores so the plan is stable
+                .rev()
+                .max_by_key(|(score, _)| *score);
+
+            match best {
+                Some((_, change)) => {
+                    graph.apply(change);
+                    changes.push(change);
+                }
+                None => break,
+            }
+        }
+
+        // Later changes can make earlier ones unnecessary (e.g. dropping a dependency that was
+        // bumped before), so remove any change the rest still succeed without. A bumped package that
+        // is no longer reachable clearly isn't needed, only dropped dependencies need to be checked.
+        let collapsed = graph.collapsed_count();
+        let mut idx = changes.len();
+        while idx > 0 {
+            idx -= 1;
+            match changes[idx] {
+                Change::Bump(id) if !graph.alive[id] => {
+                    changes.remove(idx);
+                }
+                Change::Bump(_) => {}
+                Change::Drop(..) => {
+                    let mut without = changes.clone();
+                    without.remove(idx);
+                    let without_graph = PlanGraph::with_changes(deps, parents, &without);
+
+                    if without_graph.collapsed_count() >= collapsed {
+                        changes = without;
+                        graph = without_graph;
+                    }
+                }
+            }
+        }
+
+        // Replay the changes in order of payoff, crediting each duplicate to the step that finishes it
+        let mut applied = Vec::with_capacity(changes.len());
+        let mut graph = PlanGraph::new(deps, parents);
+        let mut steps = Vec::new();
+        while let Some(group) = Self::next_group(deps, parents, &applied, &mut changes) {
+            let pending: Vec<_> = (0..graph.dups.len())
+                .filter(|&dup_idx| !graph.is_collapsed(dup_idx))
+                .collect();
+
+            let mut helps = IndexSet::new();
+            for &change in &group {
+                helps.extend(graph.helped_by(change));
+                applied.push(change);
+                graph = PlanGraph::with_changes(deps, parents, &applied);
+            }
+
+            let removes: IndexSet<_> = pending
+                .into_iter()
+                .filter(|&dup_idx| graph.is_collapsed(dup_idx))
+                .map(|dup_idx| graph.dups[dup_idx].0.clone())
+                .collect();
+            helps.retain(|name| !removes.contains(name));
+            helps.sort_unstable();
+            steps.push(PlanStep {
+                changes: group
+                    .into_iter()
+                    .map(|change| graph.plan_change(change))
+                    .collect(),
+                removes,
+                helps,
+            });
+        }
+
+        Self(steps)
+    }
+
+    /// Takes the remaining change that removes the most duplicates after the applied ones (the first on
+    /// ties). If none removes any alone, takes the fewest changes (in the order chosen) that together
+    /// remove one, so a change that only helps is listed with the one that finishes the job. If no
+    /// changes remove any together (the greedy search stopped at an older version that is top level
+    /// itself), takes the first one that still helps, or `None` once none do.
+    fn next_group(
+        deps: &Deps,
+        parents: &MultiVerDepParents,
+        applied: &[Change],
+        changes: &mut Vec<Change>,
+    ) -> Option<Vec<Change>> {
+        let collapsed_with = |extra: &[Change]| {
+            let all: Vec<_> = applied.iter().chain(extra).copied().collect();
+            PlanGraph::with_changes(deps, parents, &all).collapsed_count()
+        };
+        let collapsed = collapsed_with(&[]);
+
+        let best = changes
+            .iter()
+            .enumerate()
+            .map(|(idx, &change)| (collapsed_with(&[change]), idx))
+            .filter(|&(count, _)| count > collapsed)
+            .rev()
+            .max_by_key(|&(count, _)| count);
+        if let Some((_, idx)) = best {
+            return Some(vec![changes.remove(idx)]);
+        }
+
+        let Some(len) =
+            (2..=changes.len()).find(|&len| collapsed_with(&changes[..len]) > collapsed)
+        else {
+            let graph = PlanGraph::with_changes(deps, parents, applied);
+            let idx = changes
+                .iter()
+                .position(|&change| !graph.helped_by(change).is_empty())?;
+            return Some(vec![changes.remove(idx)]);
+        };
+        let mut group: Vec<_> = changes.drain(..len).collect();
+
+        // Leave out any change the rest of the group still removes as much without
+        let removed = collapsed_with(&group);
+        let mut idx = group.len();
+        while idx > 0 && group.len() > 1 {
+            idx -= 1;
+            let mut without = group.clone();
+            without.remove(idx);
+            if collapsed_with(&without) >= removed {
+                changes.insert(0, group.remove(idx));
+            }
+        }
+
+        Some(group)
+    }
+
+    /// The number of changes in the plan
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|step| step.changes.len()).sum()
+    }
+
+    /// The number of multi version dependencies the plan collapses to a single version
+    pub fn removed_count(&self) -> usize {
+        self.0.iter().map(|step| step.removes.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        for (idx, step) in self.0.iter().enumerate() {
+            step.render(w, idx + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::random_locks::random_locks;
+    use crate::{Config, MultiVerDeps};
+
+    use cargo_lock::Lockfile;
+
+    #[test]
+    fn every_step_removes_or_helps_on_random_graphs() {
+        for (graph, lock) in random_locks(17, 50).iter().enumerate() {
+            let deps = Deps::from_lock_file(Lockfile::from_str(lock).unwrap()).unwrap();
+            let multi_ver_deps = MultiVerDeps::from_deps(&deps, &Config::default()).unwrap();
+            let parents = MultiVerDepParents::build(&deps, &multi_ver_deps).unwrap();
+
+            for (number, step) in UpgradePlan::build(&deps, &parents).0.iter().enumerate() {
+                assert!(
+                    !step.removes.is_empty() || !step.helps.is_empty(),
+                    "random graph {graph}, step {}",
+                    number + 1
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Deterministic pseudo random lock files for checking the graph algorithms against simpler ones. The
+//! same seed always gives the same lock files, so failures can be reproduced.
+
+const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+const PACKAGES: usize = 30;
+const LOCAL_PACKAGES: usize = 3;
+
+/// Linear congruential generator (the constants are Knuth's MMIX ones)
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, max: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) as usize) % max
+    }
+}
+
+/// Lock files of the packages `pkg0` to `pkg29`, each with one to three versions that depend on up to
+/// three random versions of any package (so there are cycles of all sizes, including packages that
+/// depend on themselves). Version 1.0.0 of the first three packages is local, so they are top level
+/// and can also be duplicates of a registry version.
+pub(crate) fn random_locks(seed: u64, count: usize) -> Vec<String> {
+    let mut rng = Lcg(seed);
+
+    (0..count)
+        .map(|_| {
+            let versions: Vec<_> = (0..PACKAGES).map(|_| rng.next(3) + 1).collect();
+            let mut lock = String::from("version = 4\n");
+
+            for (idx, &version_count) in versions.iter().enumerate() {
+                for major in 1..=version_count {
+                    let mut dependencies: Vec<_> = (0..rng.next(4))
+                        .map(|_| {
+                            let dep = rng.next(PACKAGES);
+                            format!("\"pkg{dep} {}.0.0\"", rng.next(versions[dep]) + 1)
+                        })
+                        .collect();
+                    dependencies.sort();
+                    dependencies.dedup();
+
+                    let source = if idx < LOCAL_PACKAGES && major == 1 {
+                        String::new()
+                    } else {
+                        format!("source = \"{CRATES_IO}\"\n")
+                    };
+                    lock.push_str(&format!(
+                        "\n[[package]]\nname = \"pkg{idx}\"\nversion = \"{major}.0.0\"\n{source}dependencies = [{}]\n",
+                        dependencies.join(", ")
+                    ));
+                }
+            }
+
+            lock
+        })
+        .collect()
+}
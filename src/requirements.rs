@@ -0,0 +1,236 @@
+//! Reads the version requirements packages declare on their dependencies from their manifests: the
+//! cached registry sources in `$CARGO_HOME/registry/src`, a vendor directory, or the local `Cargo.toml`
+//! files. A manifest that can't be found or parsed simply leaves the requirement unknown.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::cargo_home::cargo_home;
+use crate::workspace::MANIFEST_FILE;
+use crate::{Package, Workspace};
+
+use semver::VersionReq;
+use toml::{Table, Value};
+
+/// Tables dependencies can be declared in (old manifests use underscores)
+const DEPENDENCY_TABLES: [&str; 5] = [
+    "dependencies",
+    "build-dependencies",
+    "dev-dependencies",
+    "build_dependencies",
+    "dev_dependencies",
+];
+
+/// Fields shown for dependencies that aren't declared with a plain version string
+const SPEC_FIELDS: [&str; 7] = ["package", "version", "git", "branch", "tag", "rev", "path"];
+
+fn load(path: &Path) -> Option<Table> {
+    toml::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+fn get_str<'a>(table: &'a Table, path: &[&str]) -> Option<&'a str> {
+    let (last, path) = path.split_last()?;
+    path.iter()
+        .try_fold(table, |table, key| table.get(*key)?.as_table())?
+        .get(*last)?
+        .as_str()
+}
+
+/// Every dependency declaration of the manifest, including platform specific ones
+fn dependency_specs(manifest: &Table) -> impl Iterator<Item = (&String, &Value)> {
+    let targets = manifest
+        .get("target")
+        .and_then(Value::as_table)
+        .into_iter()
+        .flat_map(|targets| targets.values().filter_map(Value::as_table));
+
+    std::iter::once(manifest)
+        .chain(targets)
+        .flat_map(|table| {
+            DEPENDENCY_TABLES
+                .iter()
+                .filter_map(|name| table.get(*name)?.as_table())
+        })
+        .flatten()
+}
+
+/// Formats the declaration the way it would be written in the manifest (a plain version string if
+/// nothing else of interest is specified)
+fn format_spec(key: &str, spec: &Value) -> String {
+    let Value::Table(table) = spec else {
+        return format!("{key} = {spec}");
+    };
+
+    let fields: Vec<_> = SPEC_FIELDS
+        .iter()
+        .filter_map(|&field| Some((field, table.get(field)?)))
+        .collect();
+    match fields.as_slice() {
+        [("version", version)] => format!("{key} = {version}"),
+        fields => {
+            let fields: Vec<_> = fields
+                .iter()
+                .map(|(field, value)| format!("{field} = {value}"))
+                .collect();
+            format!("{key} = {{ {} }}", fields.join(", "))
+        }
+    }
+}
+
+// *** Requirements ***
+
+pub struct Requirements {
+    /// The `registry/src/<index>` directories of the cargo home
+    registry_dirs: Vec<PathBuf>,
+    vendor_dir: Option<PathBuf>,
+
+    /// Local package name -> its manifest
+    local: HashMap<String, Table>,
+    /// `[workspace.dependencies]` of the workspace root (used by `workspace = true` declarations)
+    workspace_deps: Table,
+
+    /// Manifests of registry and git packages already looked up (`None` if not found)
+    remote: HashMap<Package, Option<Table>>,
+}
+
+impl Requirements {
+    pub fn new(workspace: Option<&Workspace>, vendor_dir: Option<&Path>) -> Self {
+        let registry_dirs = cargo_home()
+            .and_then(|home| std::fs::read_dir(home.join("registry").join("src")).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .collect();
+
+        let mut requirements = Self {
+            registry_dirs,
+            vendor_dir: vendor_dir.map(Path::to_path_buf),
+            local: HashMap::new(),
+            workspace_deps: Table::new(),
+            remote: HashMap::new(),
+        };
+        if let Some(workspace) = workspace {
+            requirements.load_local(workspace);
+        }
+        requirements
+    }
+
+    /// Reads the manifests of the workspace members and (recursively) their path dependencies
+    fn load_local(&mut self, workspace: &Workspace) {
+        if let Some(Value::Table(deps)) = load(&workspace.root().join(MANIFEST_FILE))
+            .as_mut()
+            .and_then(|root| {
+                root.get_mut("workspace")?
+                    .as_table_mut()?
+                    .remove("dependencies")
+            })
+        {
+            self.workspace_deps = deps;
+        }
+
+        let mut visited = HashSet::new();
+        let mut work_queue = workspace.manifest_paths().to_vec();
+
+        while let Some(path) = work_queue.pop() {
+            // Path dependencies can point back at each other, so compare the real paths
+            let Ok(path) = path.canonicalize() else {
+                continue;
+            };
+            if !visited.insert(path.clone()) {
+                continue;
+            }
+            let (Some(manifest), Some(dir)) = (load(&path), path.parent()) else {
+                continue;
+            };
+
+            for (key, spec) in dependency_specs(&manifest) {
+                let (spec, dir) = match self.inherited(key, spec) {
+                    Some(spec) => (spec, workspace.root()),
+                    None => (spec, dir),
+                };
+                if let Some(dep_path) = spec.get("path").and_then(Value::as_str) {
+                    work_queue.push(dir.join(dep_path).join(MANIFEST_FILE));
+                }
+            }
+
+            if let Some(name) = get_str(&manifest, &["package", "name"]) {
+                self.local.insert(name.to_string(), manifest);
+            }
+        }
+    }
+
+    /// The `[workspace.dependencies]` entry of a `workspace = true` declaration
+    fn inherited(&self, key: &str, spec: &Value) -> Option<&Value> {
+        match spec.get("workspace").and_then(Value::as_bool) {
+            Some(true) => self.workspace_deps.get(key),
+            _ => None,
+        }
+    }
+
+    /// Finds the manifest of a registry or git package in the vendor directory (named either
+    /// `name-version` or just `name`) or the cargo registry cache
+    fn find_remote(&self, pkg: &Package) -> Option<Table> {
+        let versioned = format!("{}-{}", pkg.name, pkg.version);
+        let version = pkg.version.to_string();
+
+        if let Some(vendor_dir) = &self.vendor_dir {
+            let manifest = load(&vendor_dir.join(&versioned).join(MANIFEST_FILE)).or_else(|| {
+                load(&vendor_dir.join(pkg.name.as_str()).join(MANIFEST_FILE))
+                    .filter(|manifest| get_str(manifest, &["package", "version"]) == Some(&version))
+            });
+            if manifest.is_some() {
+                return manifest;
+            }
+        }
+
+        // Git packages aren't extracted into the registry cache
+        if pkg.source.as_ref().is_some_and(|source| source.is_git()) {
+            return None;
+        }
+        self.registry_dirs
+            .iter()
+            .find_map(|dir| load(&dir.join(&versioned).join(MANIFEST_FILE)))
+    }
+
+    /// The requirement the dependent declares on the dependency, e.g. `syn = "1"`. When it is declared
+    /// more than once (e.g. for different platforms or renamed), the one matching the version is used.
+    pub fn requirement(&mut self, dependent: &Package, dependency: &Package) -> Option<String> {
+        let manifest = match dependent.source {
+            None => self.local.get(dependent.name.as_str())?,
+            Some(_) => {
+                if !self.remote.contains_key(dependent) {
+                    let manifest = self.find_remote(dependent);
+                    self.remote.insert(dependent.clone(), manifest);
+                }
+                self.remote[dependent].as_ref()?
+            }
+        };
+
+        let mut unversioned = None;
+        for (key, spec) in dependency_specs(manifest) {
+            let spec = self.inherited(key, spec).unwrap_or(spec);
+            let name = spec.get("package").and_then(Value::as_str).unwrap_or(key);
+            if name != dependency.name.as_str() {
+                continue;
+            }
+
+            let version = match spec {
+                Value::String(version) => Some(version.as_str()),
+                spec => spec.get("version").and_then(Value::as_str),
+            };
+            match version.map(VersionReq::parse) {
+                Some(Ok(req)) if req.matches(&dependency.version) => {
+                    return Some(format_spec(key, spec));
+                }
+                // Path and git dependencies don't need a version
+                None => {
+                    unversioned.get_or_insert_with(|| format_spec(key, spec));
+                }
+                Some(_) => {}
+            }
+        }
+
+        unversioned
+    }
+}
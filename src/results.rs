@@ -1,20 +1,112 @@
 use std::collections::VecDeque;
 
+use crate::baseline::{Baseline, BaselineRegressions};
 use crate::blame::{MultiVerDepBlame, MultiVerDepBlameEntry};
-use crate::dep_tree::Deps;
-use crate::multi_ver_deps::MultiVerDeps;
+use crate::config::{AllowEntry, Config};
+use crate::dep_tree::{Deps, PackageKind};
+use crate::inverse_tree::InverseTrees;
+use crate::multi_ver_deps::{DuplicateKind, MultiVerDeps};
 use crate::multi_ver_parents::MultiVerDepParents;
-use crate::{BlameMode, NO_DUP};
+use crate::plan::UpgradePlan;
+use crate::requirements::Requirements;
+use crate::root_causes::RootCauses;
+use crate::upgrades::Upgrades;
+use crate::{ALLOWED, BlameMode, INDIRECT, NO_DUP, Package, RegistryIndex};
+
+use cargo_lock::Name;
+use serde::Serialize;
+
+/// Version of the JSON output document. Bumped on any incompatible change to its structure
+pub const JSON_FORMAT_VERSION: u32 = 1;
+
+// *** JSON ***
+
+#[derive(Serialize)]
+struct BlameSummary {
+    count: usize,
+    direct: usize,
+    indirect: usize,
+    both: usize,
+}
+
+impl BlameSummary {
+    fn new(blame: &MultiVerDepBlame) -> Self {
+        Self {
+            count: blame.count(),
+            direct: blame.direct_count(),
+            indirect: blame.indirect_count(),
+            both: blame.both_count(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Summary {
+    total_packages: usize,
+    duplicate_packages: usize,
+    duplicate_versions: usize,
+    source_duplicate_packages: usize,
+    semver_compatible_packages: usize,
+    allowed_packages: usize,
+    root_causes: usize,
+    top_level_blame: Option<BlameSummary>,
+    local_path_blame: Option<BlameSummary>,
+    dependency_blame: Option<BlameSummary>,
+    dropped_versions: Option<usize>,
+    plan_steps: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct JsonResults<'a> {
+    format_version: u32,
+    #[serde(serialize_with = "MultiVerDeps::serialize_deps")]
+    duplicates: &'a MultiVerDeps,
+    #[serde(serialize_with = "MultiVerDeps::serialize_allowed")]
+    allowed: &'a MultiVerDeps,
+    root_causes: &'a RootCauses,
+    trees: Option<&'a InverseTrees>,
+    top_level_blame: Option<&'a MultiVerDepBlame>,
+    local_path_blame: Option<&'a MultiVerDepBlame>,
+    dependency_blame: Option<&'a MultiVerDepBlame>,
+    upgrades: Option<&'a Upgrades>,
+    plan: Option<&'a UpgradePlan>,
+    baseline_regressions: Option<&'a BaselineRegressions>,
+    cycles: &'a [Vec<Package>],
+    summary: Summary,
+}
+
+// *** MultiVerDepResults ***
 
 pub struct MultiVerDepResults {
     /// Top level packages that have multiple versions of dependencies
     top_level_blame: MultiVerDepBlame,
 
-    /// Dependency packages that have multiple versions of dependencies
+    /// Local path dependencies (outside the workspace) that have multiple versions of dependencies
+    path_blame: MultiVerDepBlame,
+
+    /// Registry/git dependency packages that have multiple versions of dependencies
     dep_blame: MultiVerDepBlame,
 
     /// Dependencies that have multiple versions and their associated direct and top level dependents
     multi_ver_deps: MultiVerDeps,
+
+    /// Multi version dependencies not induced by another one, and those they induce
+    root_causes: RootCauses,
+
+    /// Inverse dependency trees of each multi version dependency (if requested)
+    trees: Option<InverseTrees>,
+
+    /// Upgrades that move dependents off older duplicate versions (if requested)
+    upgrades: Option<Upgrades>,
+
+    /// Changes that would collapse every multi version dependency to a single version (if requested)
+    plan: Option<UpgradePlan>,
+
+    /// Differences from a previously recorded baseline (if requested)
+    regressions: Option<BaselineRegressions>,
+
+    /// Dependency cycles found while assigning blame (each in dependency order)
+    cycles: Vec<Vec<Package>>,
 }
 
 impl MultiVerDepResults {
@@ -22,9 +114,11 @@ impl MultiVerDepResults {
         deps: &Deps,
         parents: &MultiVerDepParents,
         multi_ver_deps: MultiVerDeps,
+        trees: Option<InverseTrees>,
         blame_mode: Option<BlameMode>,
     ) -> Result<Self, String> {
         let mut top_level_blame = MultiVerDepBlame::default();
+        let mut path_blame = MultiVerDepBlame::default();
         let mut dep_blame = MultiVerDepBlame::default();
 
         if let Some(blame_mode) = blame_mode {
@@ -33,59 +127,170 @@ impl MultiVerDepResults {
                 // # of top level packages
                 BlameMode::TopLevel => top_level_iter.len(),
                 // # of packages in total
-                BlameMode::All => deps.iter().len(),
+                BlameMode::All => deps.node_count(),
             };
+            let mut processed = vec![false; deps.node_count()];
             let mut work_queue = VecDeque::with_capacity(capacity);
-            work_queue.extend(top_level_iter.map(|pkg| (pkg, true)));
+            work_queue.extend(top_level_iter);
 
-            while let Some((pkg, is_top_level)) = work_queue.pop_front() {
+            while let Some(id) = work_queue.pop_front() {
                 // Only process if we haven't processed this before
-                // NOTE: Because it is not always obvious if something is top level or not
-                // we check both top level and dependency blame
-                if !top_level_blame.contains(pkg) && !dep_blame.contains(pkg) {
-                    let dep_ver = deps.get_version(pkg)?;
-                    let deps = dep_ver.dependencies();
-                    let blame = MultiVerDepBlameEntry::build(pkg, deps, parents);
-
-                    if is_top_level {
-                        top_level_blame.insert(pkg.clone(), blame);
-                    } else {
-                        dep_blame.insert(pkg.clone(), blame);
+                if !std::mem::replace(&mut processed[id], true) {
+                    let blame = MultiVerDepBlameEntry::build(id, parents, deps);
+                    let pkg = deps.package(id).clone();
+
+                    match deps.node(id).kind() {
+                        PackageKind::WorkspaceMember => top_level_blame.insert(pkg, blame),
+                        PackageKind::LocalPath => path_blame.insert(pkg, blame),
+                        PackageKind::Remote => dep_blame.insert(pkg, blame),
                     }
 
                     // Only keep recursing into tree if we want depenencies as well as top level
                     if blame_mode == BlameMode::All {
-                        // Some of these MIGHT be top level, but we always process top level first
-                        // so blame will already be assigned
-                        work_queue.extend(deps.iter().map(|dep| (dep, false)));
+                        work_queue.extend(deps.node(id).dependencies());
                     }
                 }
             }
 
             top_level_blame.sort();
+            path_blame.sort();
             dep_blame.sort();
         }
 
         Ok(Self {
             top_level_blame,
+            path_blame,
             dep_blame,
+            root_causes: RootCauses::build(deps, &multi_ver_deps),
             multi_ver_deps,
+            trees,
+            upgrades: None,
+            plan: None,
+            regressions: None,
+            cycles: parents.cycles().cloned().collect(),
         })
     }
 
+    pub(crate) fn multi_ver_deps(&self) -> &MultiVerDeps {
+        &self.multi_ver_deps
+    }
+
+    /// Blame of top level packages, local path dependencies and other dependencies
+    pub(crate) fn blame_iter(&self) -> impl Iterator<Item = &MultiVerDepBlame> {
+        [&self.top_level_blame, &self.path_blame, &self.dep_blame].into_iter()
+    }
+
+    /// Iterates over every package and the multi version dependency names it is directly to blame for
+    pub(crate) fn direct_blame_iter(&self) -> impl Iterator<Item = (&Package, &Name)> {
+        self.top_level_blame
+            .direct_iter()
+            .chain(self.path_blame.direct_iter())
+            .chain(self.dep_blame.direct_iter())
+    }
+
+    /// Allow entries of the config that did not allow any of the multi version dependencies
+    pub fn unused_allow_entries<'a>(&self, config: &'a Config) -> Vec<&'a AllowEntry> {
+        config.unused_allow_entries(self.multi_ver_deps.allowed_iter())
+    }
+
+    /// Records the current multi version dependencies and direct blame as a baseline
+    pub fn baseline(&self) -> Baseline {
+        Baseline::build(&self.multi_ver_deps, self.direct_blame_iter())
+    }
+
+    /// Compares against the baseline. Once applied, only differences from it are considered an error
+    pub fn apply_baseline(&mut self, baseline: &Baseline) {
+        self.regressions = Some(BaselineRegressions::build(baseline, &self.baseline()));
+    }
+
+    /// Looks up the version requirement declared by each package behind direct blame
+    pub fn resolve_requirements(&mut self, requirements: &mut Requirements) {
+        for blame in [
+            &mut self.top_level_blame,
+            &mut self.path_blame,
+            &mut self.dep_blame,
+        ] {
+            for (dependent, dependency, requirement) in blame.requirements_mut() {
+                *requirement = requirements.requirement(dependent, dependency);
+            }
+        }
+    }
+
+    /// Looks up upgrades of the direct dependents of older duplicate versions in the registry index
+    pub fn suggest_upgrades(&mut self, index: &mut RegistryIndex) {
+        self.upgrades = Some(Upgrades::build(&self.multi_ver_deps, index));
+    }
+
+    /// Plans the changes that would collapse every multi version dependency to a single version
+    pub fn build_plan(&mut self, deps: &Deps, parents: &MultiVerDepParents) {
+        self.plan = Some(UpgradePlan::build(deps, parents));
+    }
+
     pub fn return_error(&self, blame_mode: Option<BlameMode>) -> bool {
+        // With a baseline, only newly introduced duplicates/blame are an issue
+        if let Some(regressions) = &self.regressions {
+            return !regressions.is_empty();
+        }
+
         match blame_mode {
             // Only top level having direct blame is an issue
             Some(BlameMode::TopLevel) => self.top_level_blame.has_direct_blame(),
             // Either top level or dependencies having direct blame is an issue
             Some(BlameMode::All) => {
-                self.top_level_blame.has_direct_blame() || self.dep_blame.has_direct_blame()
+                self.top_level_blame.has_direct_blame()
+                    || self.path_blame.has_direct_blame()
+                    || self.dep_blame.has_direct_blame()
             }
             // No blame mode we just care if we have any multi version dependencies
             _ => !self.multi_ver_deps.is_empty(),
         }
     }
 
+    pub fn render_json<W: std::fmt::Write>(
+        &self,
+        w: &mut W,
+        count: usize,
+        blame_mode: Option<BlameMode>,
+    ) -> std::fmt::Result {
+        let top_level_blame = blame_mode.map(|_| &self.top_level_blame);
+        let (local_path_blame, dependency_blame) = match blame_mode {
+            Some(BlameMode::All) => (Some(&self.path_blame), Some(&self.dep_blame)),
+            _ => (None, None),
+        };
+
+        let results = JsonResults {
+            format_version: JSON_FORMAT_VERSION,
+            duplicates: &self.multi_ver_deps,
+            allowed: &self.multi_ver_deps,
+            root_causes: &self.root_causes,
+            trees: self.trees.as_ref(),
+            top_level_blame,
+            local_path_blame,
+            dependency_blame,
+            upgrades: self.upgrades.as_ref(),
+            plan: self.plan.as_ref(),
+            baseline_regressions: self.regressions.as_ref(),
+            cycles: &self.cycles,
+            summary: Summary {
+                total_packages: count,
+                duplicate_packages: self.multi_ver_deps.dup_pkg_count(),
+                duplicate_versions: self.multi_ver_deps.dup_ver_count(),
+                source_duplicate_packages: self.multi_ver_deps.kind_count(DuplicateKind::Sources),
+                semver_compatible_packages: self.multi_ver_deps.semver_compatible_count(),
+                allowed_packages: self.multi_ver_deps.allowed_count(),
+                root_causes: self.root_causes.len(),
+                top_level_blame: top_level_blame.map(BlameSummary::new),
+                local_path_blame: local_path_blame.map(BlameSummary::new),
+                dependency_blame: dependency_blame.map(BlameSummary::new),
+                dropped_versions: self.upgrades.as_ref().map(Upgrades::dropped_count),
+                plan_steps: self.plan.as_ref().map(UpgradePlan::len),
+            },
+        };
+
+        let json = serde_json::to_string_pretty(&results).map_err(|_| std::fmt::Error)?;
+        w.write_str(&json)
+    }
+
     pub fn render<W: std::fmt::Write>(
         &self,
         w: &mut W,
@@ -94,8 +299,33 @@ impl MultiVerDepResults {
         blame_detail: bool,
     ) -> std::fmt::Result {
         if !self.multi_ver_deps.is_empty() {
-            writeln!(w, "Duplicate Package(s):\n")?;
-            writeln!(w, "{}", self.multi_ver_deps)?;
+            if self.multi_ver_deps.kind_count(DuplicateKind::Versions) > 0 {
+                writeln!(w, "Duplicate Package(s):\n")?;
+                self.multi_ver_deps.render(w, DuplicateKind::Versions)?;
+                writeln!(w)?;
+            }
+
+            if self.multi_ver_deps.kind_count(DuplicateKind::Sources) > 0 {
+                writeln!(w, "Same Version, Different Source Package(s):\n")?;
+                self.multi_ver_deps.render(w, DuplicateKind::Sources)?;
+                writeln!(w)?;
+            }
+
+            if self.root_causes.induced_count() > 0 {
+                writeln!(w, "Root Cause(s):\n")?;
+                self.root_causes.render(w, &self.multi_ver_deps)?;
+                writeln!(w)?;
+            }
+
+            if self.multi_ver_deps.allowed_count() > 0 {
+                self.render_allowed(w)?;
+                writeln!(w)?;
+            }
+
+            if let Some(trees) = &self.trees {
+                writeln!(w, "Inverse Tree(s):\n")?;
+                trees.render(w)?;
+            }
 
             if blame_mode.is_some() {
                 writeln!(w, "Top Level Blame:\n")?;
@@ -103,20 +333,105 @@ impl MultiVerDepResults {
             }
 
             if let Some(BlameMode::All) = blame_mode {
+                if !self.path_blame.is_empty() {
+                    writeln!(w, "\nLocal Path Dependency Blame:\n")?;
+                    self.path_blame.render(w, blame_detail)?;
+                }
+
                 writeln!(w, "\nDependency Blame:\n")?;
                 self.dep_blame.render(w, blame_detail)?;
             }
 
+            if let Some(upgrades) = &self.upgrades {
+                // Blame doesn't end with a blank line like the sections above
+                if blame_mode.is_some() {
+                    writeln!(w)?;
+                }
+                writeln!(w, "Upgrade Suggestion(s):\n")?;
+                if upgrades.is_empty() {
+                    writeln!(w, "No upgrades found in the local registry index cache.")?;
+                } else {
+                    upgrades.render(w)?;
+                }
+            }
+
+            if let Some(plan) = &self.plan {
+                writeln!(w, "\nUpgrade Plan:\n")?;
+                plan.render(w)?;
+            }
+
+            if !self.cycles.is_empty() {
+                writeln!(w, "\nDependency Cycle(s):\n")?;
+                for cycle in &self.cycles {
+                    write!(w, "{INDIRECT}")?;
+                    for pkg in cycle {
+                        write!(w, "{pkg} -> ")?;
+                    }
+                    writeln!(w, "{}{INDIRECT:#}", cycle[0])?;
+                }
+            }
+
+            if let Some(regressions) = &self.regressions {
+                writeln!(w, "\nBaseline:\n")?;
+                if regressions.is_empty() {
+                    writeln!(w, "{NO_DUP}No new duplicates since baseline.{NO_DUP:#}")?;
+                } else {
+                    regressions.render(w)?;
+                }
+            }
+
             writeln!(w, "\nSummary:\n")?;
 
             writeln!(
                 w,
-                "{} duplicate out of {} total package(s) ({} duplicate versions)",
+                "{} duplicate out of {} total package(s) ({} duplicate versions, {} allowed)",
                 self.multi_ver_deps.dup_pkg_count(),
                 count,
                 self.multi_ver_deps.dup_ver_count(),
+                self.multi_ver_deps.allowed_count(),
+            )?;
+
+            writeln!(
+                w,
+                "{} independent root cause(s) ({} duplicate(s) induced by another)",
+                self.root_causes.len(),
+                self.root_causes.induced_count()
             )?;
 
+            let source_dups = self.multi_ver_deps.kind_count(DuplicateKind::Sources);
+            if source_dups > 0 {
+                writeln!(
+                    w,
+                    "{source_dups} duplicate(s) with the same version from different sources"
+                )?;
+            }
+
+            let compatible = self.multi_ver_deps.semver_compatible_count();
+            if compatible > 0 {
+                writeln!(
+                    w,
+                    "{compatible} duplicate(s) with semver compatible versions (try `cargo update` or removing a version pin)"
+                )?;
+            }
+
+            if let Some(upgrades) = &self.upgrades {
+                writeln!(
+                    w,
+                    "{} duplicate version(s) can be dropped by upgrading their dependents",
+                    upgrades.dropped_count()
+                )?;
+            }
+
+            if let Some(plan) = &self.plan {
+                writeln!(
+                    w,
+                    "{} change(s) would remove {} of {} duplicate(s)",
+                    plan.len(),
+                    plan.removed_count(),
+                    self.multi_ver_deps.dup_pkg_count()
+                )?;
+            }
+
             if blame_mode.is_some() {
                 writeln!(
                     w,
@@ -129,6 +444,17 @@ impl MultiVerDepResults {
             }
 
             if let Some(BlameMode::All) = blame_mode {
+                if !self.path_blame.is_empty() {
+                    writeln!(
+                        w,
+                        "{} local path dependency package(s) to blame ({} directly, {} indirectly, {} both)",
+                        self.path_blame.count(),
+                        self.path_blame.direct_count(),
+                        self.path_blame.indirect_count(),
+                        self.path_blame.both_count()
+                    )?;
+                }
+
                 writeln!(
                     w,
                     "{} dependency package(s) to blame ({} directly, {} indirectly, {} both)",
@@ -140,8 +466,18 @@ impl MultiVerDepResults {
             }
         } else {
             writeln!(w, "{NO_DUP}No duplicate dependencies found.{NO_DUP:#}")?;
+
+            if self.multi_ver_deps.allowed_count() > 0 {
+                writeln!(w)?;
+                self.render_allowed(w)?;
+            }
         }
 
         Ok(())
     }
+
+    fn render_allowed<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        writeln!(w, "{ALLOWED}Allowed Duplicate Package(s):{ALLOWED:#}\n")?;
+        self.multi_ver_deps.render_allowed(w)
+    }
 }
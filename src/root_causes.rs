@@ -0,0 +1,298 @@
+//! Groups multi version dependencies that cascade from one another. When the extra (all but the
+//! kept) versions of a duplicate are only reachable through the extra versions of another, they go
+//! away with it, e.g. an old `hyper` dragging in an old `http`, `h2` and `tokio-util`. The duplicates
+//! not induced by any other are the root causes: the number of independent problems.
+
+use std::collections::HashSet;
+
+use crate::INDIRECT;
+use crate::dep_tree::{Deps, PkgId};
+use crate::multi_ver_deps::MultiVerDeps;
+
+use cargo_lock::Name;
+use indexmap::{IndexMap, IndexSet};
+use serde::{Serialize, ser::SerializeSeq};
+
+/// Marks the packages reachable from the top level packages
+fn reachable(deps: &Deps) -> Vec<bool> {
+    let mut reached = vec![false; deps.node_count()];
+    let mut work_queue: Vec<_> = deps.top_level_iter().collect();
+
+    while let Some(id) = work_queue.pop() {
+        // Dependencies can form cycles, so never walk the same package twice
+        if std::mem::replace(&mut reached[id], true) {
+            continue;
+        }
+
+        for &dep_id in deps.node(id).dependencies() {
+            if !reached[dep_id] {
+                work_queue.push(dep_id);
+            }
+        }
+    }
+
+    reached
+}
+
+/// The reachable packages that can no longer be reached once the blocked ones are, including the
+/// blocked ones themselves. Only the packages below the blocked ones can be cut off, so only those are
+/// walked.
+fn cut_off(deps: &Deps, blocked: &[PkgId], reached: &[bool]) -> HashSet<PkgId> {
+    let blocked: HashSet<_> = blocked.iter().copied().filter(|&id| reached[id]).collect();
+
+    // Everything below the blocked packages
+    let mut below = HashSet::new();
+    let mut work_queue: Vec<_> = blocked.iter().copied().collect();
+    while let Some(id) = work_queue.pop() {
+        for &dep_id in deps.node(id).dependencies() {
+            if !blocked.contains(&dep_id) && below.insert(dep_id) {
+                work_queue.push(dep_id);
+            }
+        }
+    }
+
+    // Of those, the ones still reached from elsewhere (or top level themselves), and everything below
+    // them
+    let mut still_reached = HashSet::new();
+    let mut work_queue: Vec<_> = below
+        .iter()
+        .copied()
+        .filter(|&id| {
+            deps.node(id).is_top_level()
+                || deps.node(id).dependents().iter().any(|dependent| {
+                    reached[*dependent]
+                        && !below.contains(dependent)
+                        && !blocked.contains(dependent)
+                })
+        })
+        .collect();
+    while let Some(id) = work_queue.pop() {
+        if still_reached.insert(id) {
+            work_queue.extend(
+                deps.node(id)
+                    .dependencies()
+                    .iter()
+                    .filter(|dep_id| below.contains(dep_id) && !still_reached.contains(dep_id)),
+            );
+        }
+    }
+
+    below
+        .into_iter()
+        .filter(|id| !still_reached.contains(id))
+        .chain(blocked)
+        .collect()
+}
+
+// *** RootCauses ***
+
+/// Each root cause and the duplicates it induces
+#[derive(Default)]
+pub struct RootCauses(IndexMap<Name, IndexSet<Name>>);
+
+impl RootCauses {
+    /// Blocks the extra versions of each duplicate in turn and checks which other duplicates' extra
+    /// versions can no longer be reached. Induced duplicates are assigned to the first (by name) root
+    /// cause that induces them.
+    pub fn build(deps: &Deps, multi_ver_deps: &MultiVerDeps) -> Self {
+        let names: Vec<_> = multi_ver_deps.iter().map(|(name, _)| name).collect();
+        let induced_by = Self::induced_by(deps, multi_ver_deps);
+
+        // Inducing is transitive and can't be mutual (whichever extra version is reached first isn't
+        // induced), so every induced duplicate is also induced by at least one root cause
+        let mut root_causes: IndexMap<_, _> = names
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| induced_by[*idx].is_empty())
+            .map(|(_, name)| ((*name).clone(), IndexSet::new()))
+            .collect();
+
+        for (idx, name) in names.iter().enumerate() {
+            let root = induced_by[idx]
+                .iter()
+                .map(|&cause| names[cause])
+                .find(|cause| root_causes.contains_key(*cause))
+                .cloned();
+
+            if let Some(root) = root {
+                root_causes[&root].insert((*name).clone());
+            }
+        }
+
+        Self(root_causes)
+    }
+
+    /// The duplicates (by index) that induce each duplicate, in order
+    fn induced_by(deps: &Deps, multi_ver_deps: &MultiVerDeps) -> Vec<Vec<usize>> {
+        // The versions of each duplicate that would have to go (the kept one is the same as for the
+        // exclusive packages)
+        let dups: Vec<Vec<PkgId>> = multi_ver_deps
+            .iter()
+            .map(|(_, mv_dep)| mv_dep.extra_ids().collect())
+            .collect();
+        let mut dup_of = vec![None; deps.node_count()];
+        for (idx, extras) in dups.iter().enumerate() {
+            for &id in extras {
+                dup_of[id] = Some(idx);
+            }
+        }
+
+        let reached = reachable(deps);
+        let mut induced_by = vec![Vec::new(); dups.len()];
+        for (cause, cause_extras) in dups.iter().enumerate() {
+            let cut = cut_off(deps, cause_extras, &reached);
+
+            // Only duplicates with an extra version cut off can be induced. Versions that can't be
+            // reached at all aren't induced by anything in particular.
+            let candidates: IndexSet<_> = cut.iter().filter_map(|&id| dup_of[id]).collect();
+            for idx in candidates {
+                let extras = &dups[idx];
+                if idx != cause && extras.iter().all(|id| !reached[*id] || cut.contains(id)) {
+                    induced_by[idx].push(cause);
+                }
+            }
+        }
+
+        // Candidates come in no particular order, but the first root cause is wanted
+        for causes in &mut induced_by {
+            causes.sort_unstable();
+        }
+
+        induced_by
+    }
+
+    /// The number of independent problems: duplicates not induced by another
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The number of duplicates induced by a root cause
+    pub fn induced_count(&self) -> usize {
+        self.0.values().map(IndexSet::len).sum()
+    }
+
+    /// Renders the root causes that induce other duplicates, with the duplicates nested under them
+    pub fn render<W: std::fmt::Write>(
+        &self,
+        w: &mut W,
+        multi_ver_deps: &MultiVerDeps,
+    ) -> std::fmt::Result {
+        let versions = |name: &Name| {
+            multi_ver_deps
+                .get(name)
+                .map(ToString::to_string)
+                .unwrap_or_default()
+        };
+
+        for (root, induced) in self.0.iter().filter(|(_, induced)| !induced.is_empty()) {
+            writeln!(
+                w,
+                "{root} ({}) induces {} duplicate(s):",
+                versions(root),
+                induced.len()
+            )?;
+
+            for name in induced {
+                writeln!(w, "{INDIRECT}  {name} ({}){INDIRECT:#}", versions(name))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Serialize for RootCauses {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            name: &'a Name,
+            induced: &'a IndexSet<Name>,
+        }
+
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for (name, induced) in &self.0 {
+            seq.serialize_element(&Entry { name, induced })?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::Config;
+    use crate::random_locks::random_locks;
+
+    use cargo_lock::Lockfile;
+
+    /// The original algorithm: walk the whole graph again with each duplicate's extra versions blocked
+    fn per_dup_walk(deps: &Deps, multi_ver_deps: &MultiVerDeps) -> Vec<Vec<usize>> {
+        let reached_without = |blocked: &[PkgId]| {
+            let mut reached = vec![false; deps.node_count()];
+            let mut work_queue: Vec<_> = deps.top_level_iter().collect();
+            while let Some(id) = work_queue.pop() {
+                if !blocked.contains(&id) && !std::mem::replace(&mut reached[id], true) {
+                    work_queue.extend(deps.node(id).dependencies());
+                }
+            }
+            reached
+        };
+
+        let dups: Vec<Vec<PkgId>> = multi_ver_deps
+            .iter()
+            .map(|(_, mv_dep)| mv_dep.extra_ids().collect())
+            .collect();
+        let reached = reached_without(&[]);
+        let is_induced = |extras: &[PkgId], reached: &[bool]| extras.iter().all(|&id| !reached[id]);
+
+        let mut induced_by = vec![Vec::new(); dups.len()];
+        for (cause, cause_extras) in dups.iter().enumerate() {
+            let reached_after = reached_without(cause_extras);
+            for (idx, extras) in dups.iter().enumerate() {
+                if idx != cause
+                    && !is_induced(extras, &reached)
+                    && is_induced(extras, &reached_after)
+                {
+                    induced_by[idx].push(cause);
+                }
+            }
+        }
+        induced_by
+    }
+
+    fn assert_same_as_per_dup_walk(lock: &str, context: &str) {
+        let deps = Deps::from_lock_file(Lockfile::from_str(lock).unwrap()).unwrap();
+        let multi_ver_deps = MultiVerDeps::from_deps(&deps, &Config::default()).unwrap();
+        assert_eq!(
+            RootCauses::induced_by(&deps, &multi_ver_deps),
+            per_dup_walk(&deps, &multi_ver_deps),
+            "{context}"
+        );
+    }
+
+    #[test]
+    fn same_as_per_dup_walk_on_fixtures() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "lock") {
+                let lock = std::fs::read_to_string(&path).unwrap();
+                assert_same_as_per_dup_walk(&lock, &path.display().to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn same_as_per_dup_walk_on_random_graphs() {
+        for (graph, lock) in random_locks(13, 20).iter().enumerate() {
+            assert_same_as_per_dup_walk(lock, &format!("random graph {graph}"));
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Suggests upgrades for the packages that directly depend on an older version of a duplicate: a newer
+//! release of theirs (found in the local registry index cache) that depends on a newer version the
+//! rest of the graph already uses, or no longer depends on the duplicate at all.
+
+use crate::index::{RegistryIndex, Release};
+use crate::multi_ver_deps::{MultiVerDeps, is_semver_compatible};
+use crate::{NO_DUP, Package};
+
+use cargo_lock::{Name, SourceId, Version};
+use indexmap::IndexSet;
+use serde::Serialize;
+
+/// The version of the duplicate the release would use: the newest of the targets it allows, `Some(None)`
+/// if it doesn't depend on the duplicate at all, or `None` if it still allows the old version (or none
+/// of the targets)
+fn uses(
+    release: &Release,
+    name: &Name,
+    from: &Version,
+    targets: &[&Version],
+) -> Option<Option<Version>> {
+    let reqs: Vec<_> = release.requirements(name).collect();
+
+    if reqs.is_empty() {
+        Some(None)
+    } else if reqs.iter().any(|req| req.matches(from)) {
+        None
+    } else {
+        targets
+            .iter()
+            .find(|target| reqs.iter().any(|req| req.matches(target)))
+            .map(|target| Some((*target).clone()))
+    }
+}
+
+// *** Upgrade ***
+
+#[derive(Serialize)]
+pub(crate) struct Upgrade {
+    /// The package to upgrade (at its current version)
+    package: Package,
+    /// The release to upgrade it to
+    to: Version,
+    /// The version of the duplicate the release depends on instead (`None` if it no longer depends on it)
+    uses: Option<Version>,
+}
+
+impl Upgrade {
+    /// Finds the oldest release newer than the dependent that moves off the old version, then prefers
+    /// the newest release semver compatible with it that does too
+    fn find(
+        index: &mut RegistryIndex,
+        dependent: &Package,
+        source: &SourceId,
+        name: &Name,
+        from: &Version,
+        targets: &[&Version],
+    ) -> Option<Self> {
+        let releases = index.releases(&dependent.name, source);
+        let candidate = |release: &&Release| {
+            !release.is_yanked()
+                && release.version() > &dependent.version
+                // Only suggest pre-releases to those already using one
+                && (release.version().pre.is_empty() || !dependent.version.pre.is_empty())
+                && uses(release, name, from, targets).is_some()
+        };
+
+        let first = releases.iter().find(candidate)?;
+        let release = releases
+            .iter()
+            .rev()
+            .filter(|release| is_semver_compatible(release.version(), first.version()))
+            .find(candidate)
+            .unwrap_or(first);
+
+        Some(Self {
+            package: dependent.clone(),
+            to: release.version().clone(),
+            uses: uses(release, name, from, targets).flatten(),
+        })
+    }
+}
+
+impl std::fmt::Display for Upgrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} -> {}",
+            self.package.name,
+            self.package.display_version(),
+            self.to
+        )
+    }
+}
+
+// *** UpgradeSuggestion ***
+
+/// The upgrades that move the direct dependents of one version of a duplicate off of it
+#[derive(Serialize)]
+pub(crate) struct UpgradeSuggestion {
+    name: Name,
+    version: Version,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<SourceId>,
+    upgrades: Vec<Upgrade>,
+    /// Direct dependents no upgrade was found for (the version is only dropped if there are none)
+    remaining: IndexSet<Package>,
+}
+
+impl UpgradeSuggestion {
+    /// True if the upgrades remove the version from the graph entirely
+    pub fn drops_version(&self) -> bool {
+        self.remaining.is_empty()
+    }
+
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        let upgrades = self
+            .upgrades
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let version = Package {
+            name: self.name.clone(),
+            version: self.version.clone(),
+            source: self.source.clone(),
+        };
+
+        if self.drops_version() {
+            writeln!(w, "{NO_DUP}upgrade {upgrades} to drop {version}{NO_DUP:#}")
+        } else {
+            let remaining = self
+                .remaining
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(
+                w,
+                "upgrade {upgrades} to move off {version} (still required by {remaining})"
+            )
+        }
+    }
+}
+
+// *** Upgrades ***
+
+#[derive(Default, Serialize)]
+pub struct Upgrades(Vec<UpgradeSuggestion>);
+
+impl Upgrades {
+    /// Looks for upgrades of the direct dependents of every version of each duplicate that isn't its
+    /// newest. Only registry packages can be upgraded.
+    pub fn build(multi_ver_deps: &MultiVerDeps, index: &mut RegistryIndex) -> Self {
+        let mut suggestions = Vec::new();
+
+        for (name, mv_dep) in multi_ver_deps.iter() {
+            let versions = mv_dep.versions();
+
+            for (pkg, dependents) in mv_dep.direct_dependents_iter() {
+                // Newest first, so upgrades move as far forward as possible
+                let targets: Vec<_> = versions
+                    .iter()
+                    .rev()
+                    .filter(|version| *version > &pkg.version)
+                    .collect();
+                if targets.is_empty() {
+                    continue;
+                }
+
+                let mut upgrades = Vec::new();
+                let mut remaining = IndexSet::new();
+                for dependent in dependents {
+                    let upgrade = match &dependent.source {
+                        Some(source) if source.is_registry() => {
+                            Upgrade::find(index, dependent, source, name, &pkg.version, &targets)
+                        }
+                        _ => None,
+                    };
+
+                    match upgrade {
+                        Some(upgrade) => upgrades.push(upgrade),
+                        None => {
+                            remaining.insert(dependent.clone());
+                        }
+                    }
+                }
+
+                if !upgrades.is_empty() {
+                    suggestions.push(UpgradeSuggestion {
+                        name: name.clone(),
+                        version: pkg.version.clone(),
+                        source: pkg.source.clone(),
+                        upgrades,
+                        remaining,
+                    });
+                }
+            }
+        }
+
+        Self(suggestions)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The number of duplicate versions the suggested upgrades remove entirely
+    pub fn dropped_count(&self) -> usize {
+        self.0
+            .iter()
+            .filter(|suggestion| suggestion.drops_version())
+            .count()
+    }
+
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        for suggestion in &self.0 {
+            suggestion.render(w)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,333 @@
+//! Explains why a single package is in the dependency graph: every path from the workspace members down
+//! to each of its versions, and the packages to blame for it having more than one version.
+
+use crate::blame::{MultiVerDepBlameChain, MultiVerDepBlameVer};
+use crate::dep_tree::{Deps, PkgId};
+use crate::results::{JSON_FORMAT_VERSION, MultiVerDepResults};
+use crate::workspace::to_name;
+use crate::{DIRECT, INDIRECT, NO_DUP, Package};
+
+use cargo_lock::Name;
+use semver::VersionReq;
+use serde::Serialize;
+
+/// Parses `name` or `name@version`. A partial version like `1` or `0.3` matches all versions starting
+/// with it (the same as a cargo package ID spec).
+fn parse_spec(spec: &str) -> Result<(Name, Option<VersionReq>), String> {
+    let (name, version) = match spec.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (spec, None),
+    };
+
+    let name = to_name(name)?;
+    let req = version
+        .map(|version| {
+            VersionReq::parse(&format!("={version}"))
+                .map_err(|e| format!("Invalid version '{version}': {e}"))
+        })
+        .transpose()?;
+    Ok((name, req))
+}
+
+/// Finds the paths from the top level packages down to the target, shortest first. Paths start at a top
+/// level package and never pass through another one (it has paths of its own). Enumerating paths of
+/// one length at a time keeps this cheap when only the first few of a huge number are wanted.
+fn find_paths(deps: &Deps, target: PkgId, max_paths: usize) -> Vec<Vec<PkgId>> {
+    // Distance of each package down to the target, found by walking up its dependents
+    let mut dist = vec![None; deps.node_count()];
+    let mut queue = std::collections::VecDeque::from([target]);
+    dist[target] = Some(0);
+
+    while let Some(id) = queue.pop_front() {
+        if id != target && deps.node(id).is_top_level() {
+            continue;
+        }
+
+        for &dependent in deps.node(id).dependents() {
+            if dist[dependent].is_none() {
+                dist[dependent] = dist[id].map(|dist| dist + 1);
+                queue.push_back(dependent);
+            }
+        }
+    }
+
+    let mut starts: Vec<_> = deps
+        .top_level_iter()
+        .filter(|&id| dist[id].is_some())
+        .collect();
+    starts.sort_unstable_by_key(|&id| deps.package(id));
+
+    // A simple path can't be longer than the number of packages that lead to the target
+    let max_len = dist.iter().flatten().count();
+    let mut paths = Vec::new();
+
+    for len in 0..max_len {
+        for &start in &starts {
+            let mut path = vec![start];
+            paths_of_len(deps, &dist, target, len, &mut path, &mut paths, max_paths);
+        }
+
+        if paths.len() >= max_paths {
+            break;
+        }
+    }
+
+    paths.truncate(max_paths);
+    paths
+}
+
+/// Depth first search for paths with exactly `remaining` more dependencies to the target
+fn paths_of_len(
+    deps: &Deps,
+    dist: &[Option<usize>],
+    target: PkgId,
+    remaining: usize,
+    path: &mut Vec<PkgId>,
+    paths: &mut Vec<Vec<PkgId>>,
+    max_paths: usize,
+) {
+    let id = path[path.len() - 1];
+    if id == target {
+        if remaining == 0 {
+            paths.push(path.clone());
+        }
+        return;
+    }
+
+    for &dep_id in deps.node(id).dependencies() {
+        if paths.len() >= max_paths {
+            return;
+        }
+
+        // Only follow dependencies that can still reach the target in time
+        let reachable = dist[dep_id].is_some_and(|dist| dist < remaining);
+        if reachable
+            && (dep_id == target || !deps.node(dep_id).is_top_level())
+            && !path.contains(&dep_id)
+        {
+            path.push(dep_id);
+            paths_of_len(deps, dist, target, remaining - 1, path, paths, max_paths);
+            path.pop();
+        }
+    }
+}
+
+// *** WhyVersion ***
+
+#[derive(Serialize)]
+struct WhyVersion {
+    #[serde(flatten)]
+    package: Package,
+    /// Paths from the top level packages down to this version, shortest first
+    paths: Vec<Vec<Package>>,
+    /// True if there are more paths than shown
+    truncated: bool,
+}
+
+impl WhyVersion {
+    fn render<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        let more = if self.truncated { "+" } else { "" };
+        writeln!(
+            w,
+            "{} ({}{more} path(s)):\n",
+            self.package,
+            self.paths.len()
+        )?;
+
+        for path in &self.paths {
+            let path = path
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            writeln!(w, "  {path}")?;
+        }
+
+        if self.truncated {
+            writeln!(
+                w,
+                "  ... (only the {} shortest paths are shown, see --max-paths)",
+                self.paths.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// *** Why ***
+
+#[derive(Serialize)]
+struct DirectBlame<'a> {
+    package: &'a Package,
+    /// Only the versions shown
+    versions: MultiVerDepBlameVer,
+}
+
+#[derive(Serialize)]
+struct IndirectBlame<'a> {
+    package: &'a Package,
+    #[serde(flatten)]
+    chain: &'a MultiVerDepBlameChain,
+}
+
+#[derive(Serialize)]
+pub struct Why<'a> {
+    format_version: u32,
+    name: Name,
+    /// True if the config allows multiple versions of the package (so it isn't blamed)
+    allowed: bool,
+    versions: Vec<WhyVersion>,
+    /// The number of versions in the lock file (including those not shown)
+    #[serde(skip)]
+    version_count: usize,
+    direct_blame: Vec<DirectBlame<'a>>,
+    indirect_blame: Vec<IndirectBlame<'a>>,
+}
+
+impl<'a> Why<'a> {
+    /// Explains the package given as `name` or `name@version`, using the blame in the results (which
+    /// must have been built with `BlameMode::All`)
+    pub fn build(
+        deps: &Deps,
+        results: &'a MultiVerDepResults,
+        spec: &str,
+        max_paths: usize,
+    ) -> Result<Self, String> {
+        let (name, req) = parse_spec(spec)?;
+        let dep = deps
+            .get(&name)
+            .ok_or(format!("Package '{name}' not found in the lock file"))?;
+
+        let mut ids: Vec<_> = dep
+            .iter()
+            .filter(|&id| {
+                req.as_ref()
+                    .is_none_or(|req| req.matches(&deps.package(id).version))
+            })
+            .collect();
+        if ids.is_empty() {
+            let versions = dep
+                .versions()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(format!(
+                "No version of '{spec}' found in the lock file (versions: {versions})"
+            ));
+        }
+        ids.sort_unstable_by_key(|&id| deps.package(id));
+
+        let versions: Vec<_> = ids
+            .into_iter()
+            .map(|id| {
+                // Ask for one more path than shown to know if there are more
+                let mut paths = find_paths(deps, id, max_paths.saturating_add(1));
+                let truncated = paths.len() > max_paths;
+                paths.truncate(max_paths);
+
+                WhyVersion {
+                    package: deps.package(id).clone(),
+                    paths: paths
+                        .into_iter()
+                        .map(|path| {
+                            path.into_iter()
+                                .map(|id| deps.package(id).clone())
+                                .collect()
+                        })
+                        .collect(),
+                    truncated,
+                }
+            })
+            .collect();
+
+        // Only blame for the versions shown is relevant
+        let is_shown = |pkg: &Package| {
+            versions
+                .iter()
+                .any(|version: &WhyVersion| version.package == *pkg)
+        };
+        let mut direct_blame = Vec::new();
+        let mut indirect_blame = Vec::new();
+        for blame in results.blame_iter() {
+            direct_blame.extend(blame.direct_for(&name).filter_map(|(package, versions)| {
+                let mut versions = versions.clone();
+                versions.retain_versions(is_shown);
+                (!versions.is_empty()).then_some(DirectBlame { package, versions })
+            }));
+            indirect_blame.extend(
+                blame
+                    .indirect_for(&name)
+                    .filter(|(_, chain)| {
+                        versions
+                            .iter()
+                            .any(|version| chain.carries(&version.package))
+                    })
+                    .map(|(package, chain)| IndirectBlame { package, chain }),
+            );
+        }
+
+        Ok(Self {
+            format_version: JSON_FORMAT_VERSION,
+            allowed: results
+                .multi_ver_deps()
+                .allowed_iter()
+                .any(|(allowed, _)| *allowed == name),
+            name,
+            versions,
+            version_count: dep.iter().count(),
+            direct_blame,
+            indirect_blame,
+        })
+    }
+
+    pub fn render_json<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        let json = serde_json::to_string_pretty(self).map_err(|_| std::fmt::Error)?;
+        w.write_str(&json)
+    }
+
+    pub fn render<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result {
+        for version in &self.versions {
+            version.render(w)?;
+            writeln!(w)?;
+        }
+
+        if self.allowed {
+            return writeln!(
+                w,
+                "{NO_DUP}Multiple versions of {} are allowed by the config, so nothing is to blame.{NO_DUP:#}",
+                self.name
+            );
+        }
+        if self.version_count == 1 {
+            return writeln!(
+                w,
+                "{NO_DUP}{} has a single version, so nothing is to blame.{NO_DUP:#}",
+                self.name
+            );
+        }
+        if self.direct_blame.is_empty() && self.indirect_blame.is_empty() {
+            return writeln!(
+                w,
+                "{NO_DUP}Nothing in the analyzed packages is to blame for the versions of {} shown.{NO_DUP:#}",
+                self.name
+            );
+        }
+
+        writeln!(w, "Direct Blame:\n")?;
+        for blame in &self.direct_blame {
+            writeln!(w, "{DIRECT}{}{DIRECT:#}", blame.package)?;
+            blame.versions.render(w, &self.name)?;
+        }
+
+        if !self.indirect_blame.is_empty() {
+            writeln!(w, "\nIndirect Blame:\n")?;
+            for blame in &self.indirect_blame {
+                writeln!(w, "{INDIRECT}{}{INDIRECT:#}", blame.package)?;
+                blame.chain.render(w, &self.name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
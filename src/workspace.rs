@@ -0,0 +1,248 @@
+//! The workspace layout (root and members) as reported by `cargo metadata`, or parsed directly from
+//! the workspace manifest when only a lock file is given
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{EdgeFilter, Package};
+
+use cargo_lock::Name;
+use indexmap::IndexSet;
+use serde::Deserialize;
+
+pub(crate) const MANIFEST_FILE: &str = "Cargo.toml";
+
+pub(crate) fn to_name(name: &str) -> Result<Name, String> {
+    Name::from_str(name).map_err(|e| format!("Invalid package name '{name}': {e}"))
+}
+
+/// Matches a single path component against a pattern containing `*` and `?` wildcards
+fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.split_first(), text.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            wildcard_match(rest, text) || (!text.is_empty() && wildcard_match(pattern, &text[1..]))
+        }
+        (Some((b'?', rest)), Some((_, text_rest))) => wildcard_match(rest, text_rest),
+        (Some((p, rest)), Some((t, text_rest))) if p == t => wildcard_match(rest, text_rest),
+        _ => false,
+    }
+}
+
+/// Expands a workspace member path that may contain wildcards into the matching directories
+fn expand_glob(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut paths = vec![root.to_path_buf()];
+
+    for component in Path::new(pattern).components() {
+        let component = component.as_os_str().to_string_lossy();
+
+        if component.contains(['*', '?']) {
+            paths = paths
+                .iter()
+                .filter_map(|path| std::fs::read_dir(path).ok())
+                .flatten()
+                .filter_map(Result::ok)
+                .filter(|entry| {
+                    wildcard_match(
+                        component.as_bytes(),
+                        entry.file_name().to_string_lossy().as_bytes(),
+                    )
+                })
+                .map(|entry| entry.path())
+                .collect();
+            paths.sort_unstable();
+        } else {
+            paths.iter_mut().for_each(|path| path.push(&*component));
+        }
+    }
+
+    paths.retain(|path| path.is_dir());
+    paths
+}
+
+// *** Manifest ***
+
+#[derive(Deserialize)]
+struct Manifest {
+    package: Option<ManifestPackage>,
+    workspace: Option<ManifestWorkspace>,
+}
+
+#[derive(Deserialize)]
+struct ManifestPackage {
+    name: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ManifestWorkspace {
+    #[serde(default)]
+    members: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    default_members: Option<Vec<String>>,
+}
+
+impl Manifest {
+    fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read '{}': {e}", path.display()))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid manifest '{}': {e}", path.display()))
+    }
+}
+
+// *** Workspace ***
+
+pub struct Workspace {
+    root: PathBuf,
+
+    /// Names of all workspace members
+    members: IndexSet<Name>,
+
+    /// Names of the members selected for analysis (via `--package`, `--workspace` and `--exclude`)
+    selected: IndexSet<Name>,
+
+    /// Which dependency edges to follow (all if `None`)
+    edges: Option<EdgeFilter>,
+
+    /// Manifests of the local packages that are known (at least the workspace members)
+    manifest_paths: Vec<PathBuf>,
+}
+
+impl Workspace {
+    /// Builds the workspace from `cargo metadata` output. `selected` are the members chosen for analysis
+    pub fn from_metadata(
+        metadata: &cargo_metadata::Metadata,
+        selected: &[&cargo_metadata::Package],
+    ) -> Result<Self, String> {
+        let members = metadata
+            .workspace_packages()
+            .into_iter()
+            .map(|pkg| to_name(&pkg.name))
+            .collect::<Result<_, String>>()?;
+        let selected = selected
+            .iter()
+            .map(|pkg| to_name(&pkg.name))
+            .collect::<Result<_, String>>()?;
+        // Without `--no-deps` this includes path dependencies outside the workspace as well
+        let manifest_paths = metadata
+            .packages
+            .iter()
+            .filter(|pkg| pkg.source.is_none())
+            .map(|pkg| pkg.manifest_path.clone().into_std_path_buf())
+            .collect();
+
+        Ok(Self {
+            root: metadata.workspace_root.clone().into_std_path_buf(),
+            members,
+            selected,
+            edges: None,
+            manifest_paths,
+        })
+    }
+
+    /// Parses the `Cargo.toml` in the given directory (if present), resolving the `members`,
+    /// `exclude` and `default-members` of its `[workspace]` section. The default members (or all
+    /// members if not specified) are selected for analysis.
+    pub fn from_manifest(root: &Path) -> Result<Option<Self>, String> {
+        let manifest_path = root.join(MANIFEST_FILE);
+        if !manifest_path.is_file() {
+            return Ok(None);
+        }
+
+        let manifest = Manifest::load(&manifest_path)?;
+        let workspace = manifest.workspace.unwrap_or_default();
+        let excluded: Vec<_> = workspace
+            .exclude
+            .iter()
+            .map(|path| root.join(path))
+            .collect();
+
+        let mut manifest_paths = Vec::new();
+        if manifest.package.is_some() {
+            manifest_paths.push(manifest_path.clone());
+        }
+
+        // Returns the package name (and manifest) of each member directory matching the patterns
+        let mut member_names = |patterns: &[String]| -> Result<IndexSet<Name>, String> {
+            let mut names = IndexSet::new();
+
+            for pattern in patterns {
+                for path in expand_glob(root, pattern) {
+                    if excluded.iter().any(|excluded| path.starts_with(excluded)) {
+                        continue;
+                    }
+
+                    let member_manifest = path.join(MANIFEST_FILE);
+                    if member_manifest.is_file()
+                        && let Some(pkg) = Manifest::load(&member_manifest)?.package
+                    {
+                        names.insert(to_name(&pkg.name)?);
+                        if !manifest_paths.contains(&member_manifest) {
+                            manifest_paths.push(member_manifest);
+                        }
+                    }
+                }
+            }
+
+            Ok(names)
+        };
+
+        // The root package (if any) is always a member
+        let mut members = member_names(&workspace.members)?;
+        if let Some(pkg) = &manifest.package {
+            members.insert(to_name(&pkg.name)?);
+        }
+
+        let selected = match &workspace.default_members {
+            Some(default_members) => member_names(default_members)?,
+            None => members.clone(),
+        };
+
+        Ok(Some(Self {
+            root: root.to_path_buf(),
+            members,
+            selected,
+            edges: None,
+            manifest_paths,
+        }))
+    }
+
+    /// Only follows the dependency edges allowed by the filter
+    pub fn with_edges(mut self, edges: EdgeFilter) -> Self {
+        self.edges = Some(edges);
+        self
+    }
+
+    /// The lock file is always shared by the whole workspace, so it lives in the root
+    pub fn lock_path(&self) -> PathBuf {
+        self.root.join("Cargo.lock")
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn manifest_paths(&self) -> &[PathBuf] {
+        &self.manifest_paths
+    }
+
+    /// Names of the members selected for analysis
+    pub fn selected_names(&self) -> Vec<&str> {
+        self.selected.iter().map(Name::as_str).collect()
+    }
+
+    pub fn is_member(&self, pkg: &Package) -> bool {
+        pkg.source.is_none() && self.members.contains(&pkg.name)
+    }
+
+    pub fn is_selected(&self, pkg: &Package) -> bool {
+        pkg.source.is_none() && self.selected.contains(&pkg.name)
+    }
+
+    pub fn allows_edge(&self, dependent: &Package, dependency: &Package) -> bool {
+        self.edges
+            .as_ref()
+            .is_none_or(|edges| edges.allows(dependent, dependency))
+    }
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{CRATES_IO, GIT, mid_lock as lock};
+use serde_json::json;
+
+/// The baseline regressions of the current lock file against a baseline of the old one
+fn regressions(old: &str, new: &str) -> serde_json::Value {
+    let (_, _, old_results) = common::analyze(old);
+    let (deps, _, mut results) = common::analyze(new);
+    results.apply_baseline(&old_results.baseline());
+    common::json(&results, deps.count())["baseline_regressions"].clone()
+}
+
+#[test]
+fn unchanged_lock_file_has_no_regressions() {
+    let lock = lock("1.0.0", CRATES_IO);
+    let (deps, _, mut results) = common::analyze(&lock);
+    results.apply_baseline(&results.baseline());
+
+    assert!(!results.return_error(None));
+    assert_eq!(
+        common::json(&results, deps.count())["baseline_regressions"],
+        json!({"new_duplicates": [], "new_versions": {}, "new_direct_blame": {}})
+    );
+}
+
+#[test]
+fn new_source_of_same_version_is_a_regression() {
+    let regressions = regressions(&lock("1.0.0", CRATES_IO), &lock("1.0.0", GIT));
+
+    assert_eq!(
+        regressions["new_versions"],
+        json!({ "dup": [format!("2.0.0 ({GIT})")] })
+    );
+    assert_eq!(regressions["new_duplicates"], json!([]));
+}
+
+#[test]
+fn newly_blamed_version_is_a_regression() {
+    let regressions = regressions(&lock("1.0.0", CRATES_IO), &lock("2.0.0", CRATES_IO));
+
+    assert_eq!(
+        regressions["new_direct_blame"],
+        json!({ "mid 2.0.0": ["dup"] })
+    );
+    assert_eq!(regressions["new_versions"], json!({}));
+}
+
+#[test]
+fn baseline_round_trips_sources() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("baseline_round_trip.json");
+    let (_, _, old_results) = common::analyze(&lock("1.0.0", GIT));
+    old_results.baseline().save(&path).unwrap();
+    let baseline = cargo_depcheck::Baseline::load(&path).unwrap();
+
+    let (_, _, mut results) = common::analyze(&lock("1.0.0", GIT));
+    results.apply_baseline(&baseline);
+    assert!(!results.return_error(None));
+}
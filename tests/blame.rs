@@ -0,0 +1,175 @@
+mod common;
+
+use std::str::FromStr;
+
+use cargo_depcheck::{
+    BlameMode, Config, Deps, MultiVerDepParents, MultiVerDepResults, MultiVerDeps, Requirements,
+    Workspace,
+};
+use cargo_lock::Lockfile;
+use common::{CRATES_IO, mid_lock};
+use serde_json::{Value, json};
+
+/// Every blame entry in the JSON output of the lock file
+fn blame_entries(lock: &str) -> Vec<Value> {
+    let (deps, _, results) = common::analyze(lock);
+    let json = common::json(&results, deps.count());
+
+    json["top_level_blame"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .chain(json["dependency_blame"].as_array().unwrap())
+        .cloned()
+        .collect()
+}
+
+/// The blame entry of the package in the JSON output of the fixture
+fn blame(fixture: &str, package: &str) -> Value {
+    blame_entries(&common::fixture(fixture))
+        .into_iter()
+        .find(|entry| entry["package"]["name"] == package)
+        .unwrap_or_else(|| panic!("{package} is not blamed"))
+}
+
+#[test]
+fn depending_directly_on_two_versions_is_direct_blame() {
+    let app = blame("git_source.lock", "app");
+    let bar = app["direct"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|direct| direct["name"] == "bar")
+        .expect("app is directly to blame for bar");
+
+    let app_requires =
+        json!([{"dependent": {"name": "app", "version": "0.1.0"}, "requirement": null}]);
+    let versions = bar["versions"].as_array().unwrap();
+    assert_eq!(versions.len(), 2);
+    for version in versions {
+        assert_eq!(version["version"], "1.2.0");
+        assert_eq!(version["requirements"], app_requires);
+    }
+}
+
+/// The requirements `app` declares on each version of the dependency in the `requirements` fixture
+/// workspace, resolved from its manifests
+fn app_requirements(name: &str) -> Vec<(Value, Value)> {
+    let root = common::fixture_path("requirements");
+    let workspace = Workspace::from_manifest(&root).unwrap().unwrap();
+    let lock = Lockfile::from_str(&std::fs::read_to_string(workspace.lock_path()).unwrap());
+    let deps = Deps::from_lock_file(lock.unwrap())
+        .unwrap()
+        .with_workspace(&workspace)
+        .unwrap();
+
+    let multi_ver_deps = MultiVerDeps::from_deps(&deps, &Config::default()).unwrap();
+    let parents = MultiVerDepParents::build(&deps, &multi_ver_deps).unwrap();
+    let mut results =
+        MultiVerDepResults::build(&deps, &parents, multi_ver_deps, None, Some(BlameMode::All))
+            .unwrap();
+    results.resolve_requirements(&mut Requirements::new(Some(&workspace), None));
+
+    let json = common::json(&results, deps.count());
+    let app = &json["top_level_blame"][0];
+    assert_eq!(app["package"]["name"], "app");
+    let direct = app["direct"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|direct| direct["name"] == name)
+        .unwrap_or_else(|| panic!("app is not directly to blame for {name}"));
+
+    direct["versions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|version| {
+            let requirements = version["requirements"].as_array().unwrap();
+            assert_eq!(requirements.len(), 1);
+            assert_eq!(requirements[0]["dependent"]["name"], "app");
+            (
+                version["version"].clone(),
+                requirements[0]["requirement"].clone(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn requirements_are_read_from_the_manifest() {
+    // Plain and renamed declarations (only the fields of interest are shown)
+    assert_eq!(
+        app_requirements("syn"),
+        [
+            (json!("1.0.109"), json!("syn = \"1\"")),
+            (
+                json!("2.0.0"),
+                json!("syn2 = { package = \"syn\", version = \"2\" }")
+            ),
+        ]
+    );
+    // Inherited from `[workspace.dependencies]` and target specific declarations of the same name
+    assert_eq!(
+        app_requirements("bitflags"),
+        [
+            (json!("1.3.2"), json!("bitflags = \"1.3\"")),
+            (json!("2.4.0"), json!("bitflags = \"2\"")),
+        ]
+    );
+}
+
+#[test]
+fn direct_blame_has_no_indirect_chain() {
+    for package in ["app", "helper"] {
+        let blame = blame("git_source.lock", package);
+        assert_eq!(blame["indirect"], json!([]), "{package}");
+        assert_eq!(blame["indirect_chains"], json!([]), "{package}");
+    }
+}
+
+#[test]
+fn indirect_chains_end_at_direct_blame() {
+    let mut locks: Vec<_> = common::lock_fixtures()
+        .iter()
+        .map(|fixture| common::fixture(fixture))
+        .collect();
+    locks.push(mid_lock("1.0.0", CRATES_IO));
+
+    let mut chains = 0;
+    for lock in &locks {
+        let entries = blame_entries(lock);
+        let direct_names = |package: &Value| -> Vec<Value> {
+            entries
+                .iter()
+                .find(|entry| entry["package"] == *package)
+                .map(|entry| {
+                    entry["direct"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|direct| direct["name"].clone())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        for entry in &entries {
+            for chain in entry["indirect_chains"].as_array().unwrap() {
+                let (last, above) = chain["chain"].as_array().unwrap().split_last().unwrap();
+                assert!(
+                    direct_names(last).contains(&chain["name"]),
+                    "{chain:#}\n{lock}"
+                );
+                for package in above {
+                    assert!(
+                        !direct_names(package).contains(&chain["name"]),
+                        "{chain:#}\n{lock}"
+                    );
+                }
+                chains += 1;
+            }
+        }
+    }
+    assert!(chains > 0);
+}
@@ -0,0 +1,308 @@
+//! Runs the binary on the fixture lock files in scratch directories
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+fn copy_fixture(fixture: &str, to: &Path) {
+    std::fs::copy(Path::new(FIXTURES).join(fixture), to).unwrap();
+}
+
+fn depcheck(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cargo-depcheck"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn diff_warns_about_unused_allow_entries_once() {
+    let dir = common::scratch_dir("diff_warns_about_unused_allow_entries_once");
+    copy_fixture("lockfile_v3.lock", &dir.join("old.lock"));
+    copy_fixture("upgrades.lock", &dir.join("Cargo.lock"));
+    std::fs::write(
+        dir.join("depcheck.toml"),
+        "allow = [\"not-a-dependency\"]\n",
+    )
+    .unwrap();
+
+    let output = depcheck(&dir, &["diff", "old.lock", "Cargo.lock"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.matches("Allow entry 'not-a-dependency'").count(),
+        1,
+        "{stderr}"
+    );
+}
+
+#[test]
+fn lock_file_without_manifest_is_analyzed() {
+    // Outside of this crate, so `cargo metadata` can't find its manifest in a parent directory
+    let dir = common::outside_scratch_dir("lock_file_without_manifest_is_analyzed");
+    copy_fixture("upgrades.lock", &dir.join("Cargo.lock"));
+
+    let output = depcheck(&dir, &[]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stdout.contains("Duplicate Package(s)"), "{stdout}");
+    assert!(!stderr.contains("Error"), "{stderr}");
+}
+
+#[test]
+fn subcommand_name_passed_by_cargo_is_skipped() {
+    let dir = common::scratch_dir("subcommand_name_passed_by_cargo_is_skipped");
+    copy_fixture("lockfile_v3.lock", &dir.join("old.lock"));
+    copy_fixture("upgrades.lock", &dir.join("new.lock"));
+
+    // `cargo depcheck diff ..` runs `cargo-depcheck depcheck diff ..`
+    let via_cargo = depcheck(&dir, &["depcheck", "diff", "old.lock", "new.lock"]);
+    let direct = depcheck(&dir, &["diff", "old.lock", "new.lock"]);
+    assert_eq!(via_cargo.stdout, direct.stdout);
+    assert!(
+        String::from_utf8_lossy(&direct.stdout).contains("Added Duplicate(s)"),
+        "{}",
+        String::from_utf8_lossy(&direct.stderr)
+    );
+}
+
+fn generate_lockfile(dir: &Path) {
+    let status = Command::new(env!("CARGO"))
+        .current_dir(dir)
+        .args(["generate-lockfile", "--offline"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+/// Writes an empty library package with the given dependency sections
+fn write_package(dir: &Path, name: &str, version: &str, sections: &str) {
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src/lib.rs"), "").unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"{name}\"\nversion = \"{version}\"\nedition = \"2021\"\n\n{sections}"
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn build_kind_follows_normal_dependencies_of_build_dependencies() {
+    // Outside of this crate, so `cargo` doesn't pick up its workspace
+    let dir =
+        common::outside_scratch_dir("build_kind_follows_normal_dependencies_of_build_dependencies");
+    let ws = dir.join("ws");
+    std::fs::create_dir_all(&ws).unwrap();
+    std::fs::write(ws.join("Cargo.toml"), "[workspace]\nmembers = [\"app\"]\n").unwrap();
+    write_package(
+        &ws.join("app"),
+        "app",
+        "0.1.0",
+        "[build-dependencies]\nbuilder = { path = \"../../builder\" }\nhelper = { path = \"../../helper2\" }\n",
+    );
+    write_package(
+        &dir.join("builder"),
+        "builder",
+        "0.1.0",
+        "[dependencies]\nhelper = { path = \"../helper1\" }\n",
+    );
+    write_package(&dir.join("helper1"), "helper", "1.0.0", "");
+    write_package(&dir.join("helper2"), "helper", "2.0.0", "");
+    generate_lockfile(&ws);
+
+    let output = depcheck(&ws, &["--kind", "build"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stdout.contains("helper (1.0.0, 2.0.0)"),
+        "{stdout}\n{stderr}"
+    );
+}
+
+/// Writes a workspace in `ws` of `app` (depending on `helper` 1.0.0) and `tool` (depending on
+/// `helper` 2.0.0), with only `app` as a default member, and generates its lock file. The `helper`
+/// packages are outside the workspace directory, or else they would be members too.
+fn write_default_members_workspace(dir: &Path) -> PathBuf {
+    let ws = dir.join("ws");
+    std::fs::create_dir_all(&ws).unwrap();
+    std::fs::write(
+        ws.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"app\", \"tool\"]\ndefault-members = [\"app\"]\nresolver = \"2\"\n",
+    )
+    .unwrap();
+    write_package(
+        &ws.join("app"),
+        "app",
+        "0.1.0",
+        "[dependencies]\nhelper = { path = \"../../helper1\" }\n",
+    );
+    write_package(
+        &ws.join("tool"),
+        "tool",
+        "0.1.0",
+        "[dependencies]\nhelper = { path = \"../../helper2\" }\n",
+    );
+    write_package(&dir.join("helper1"), "helper", "1.0.0", "");
+    write_package(&dir.join("helper2"), "helper", "2.0.0", "");
+
+    generate_lockfile(&ws);
+    ws
+}
+
+#[test]
+fn default_run_only_analyzes_the_default_members() {
+    let dir = write_default_members_workspace(&common::scratch_dir(
+        "default_run_only_analyzes_the_default_members",
+    ));
+
+    let output = depcheck(&dir, &[]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains("No duplicate"), "{stdout}");
+
+    let output = depcheck(&dir, &["--workspace"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!output.status.success());
+    assert!(stdout.contains("helper (1.0.0, 2.0.0)"), "{stdout}");
+
+    // Without the manifest every local package is top level, `tool` included
+    let lock_only = common::scratch_dir("default_run_only_analyzes_the_default_members_lock_only");
+    std::fs::copy(dir.join("Cargo.lock"), lock_only.join("Cargo.lock")).unwrap();
+    let output = depcheck(&lock_only, &[]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("helper (1.0.0, 2.0.0)"), "{stdout}");
+}
+
+#[test]
+fn default_run_in_a_member_directory_analyzes_that_member() {
+    let dir = write_default_members_workspace(&common::scratch_dir(
+        "default_run_in_a_member_directory_analyzes_that_member",
+    ));
+
+    // The lock file is read from the workspace root, and `tool` doesn't depend on `app`
+    let output = depcheck(&dir.join("tool"), &["why", "helper"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stdout.contains("tool 0.1.0 -> helper 2.0.0"),
+        "{stdout}\n{stderr}"
+    );
+    assert!(!stdout.contains("helper 1.0.0"), "{stdout}");
+}
+
+#[test]
+fn lock_file_of_another_workspace_is_an_error() {
+    let dir = common::scratch_dir("lock_file_of_another_workspace_is_an_error");
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"other\"]\n",
+    )
+    .unwrap();
+    write_package(&dir.join("other"), "other", "0.1.0", "");
+    copy_fixture("upgrades.lock", &dir.join("Cargo.lock"));
+
+    // Rather than an empty graph with no duplicates
+    let output = depcheck(&dir, &["--lock-path", "Cargo.lock"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("None of the selected workspace members (other) are in the lock file"),
+        "{stderr}"
+    );
+}
+
+/// Writes a workspace in `ws` with only `app` as a member and the given dependency sections, with
+/// `helper` 1.0.0 and 2.0.0 and `shim` (depending on `helper` 1.0.0) next to it, and generates its
+/// lock file
+fn write_app_workspace(dir: &Path, sections: &str) -> PathBuf {
+    let ws = dir.join("ws");
+    std::fs::create_dir_all(&ws).unwrap();
+    std::fs::write(
+        ws.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"app\"]\nresolver = \"2\"\n",
+    )
+    .unwrap();
+    write_package(&ws.join("app"), "app", "0.1.0", sections);
+    write_package(&dir.join("helper1"), "helper", "1.0.0", "");
+    write_package(&dir.join("helper2"), "helper", "2.0.0", "");
+    write_package(
+        &dir.join("shim"),
+        "shim",
+        "0.1.0",
+        "[dependencies]\nhelper = { path = \"../helper1\" }\n",
+    );
+    generate_lockfile(&ws);
+    ws
+}
+
+/// True if the run in the directory with the arguments reports `helper` as a duplicate
+fn has_helper_duplicate(dir: &Path, args: &[&str]) -> bool {
+    let output = depcheck(dir, args);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("Error"), "{args:?}: {stderr}");
+    stdout.contains("helper (1.0.0, 2.0.0)")
+}
+
+#[test]
+fn dependencies_of_other_targets_are_pruned() {
+    let dir = common::outside_scratch_dir("dependencies_of_other_targets_are_pruned");
+    let ws = write_app_workspace(
+        &dir,
+        "[dependencies]\nhelper = { path = \"../../helper2\" }\n\n\
+         [target.'cfg(windows)'.dependencies]\nshim = { path = \"../../shim\" }\n",
+    );
+
+    assert!(has_helper_duplicate(&ws, &[]));
+    assert!(!has_helper_duplicate(
+        &ws,
+        &["--target", "x86_64-unknown-linux-gnu"]
+    ));
+    assert!(has_helper_duplicate(
+        &ws,
+        &["--target", "x86_64-pc-windows-msvc"]
+    ));
+    // Any of the targets keeps the dependency
+    assert!(has_helper_duplicate(
+        &ws,
+        &[
+            "--target",
+            "x86_64-unknown-linux-gnu",
+            "--target",
+            "x86_64-pc-windows-msvc"
+        ]
+    ));
+    assert_eq!(
+        has_helper_duplicate(&ws, &["--target", "host"]),
+        cfg!(windows)
+    );
+}
+
+#[test]
+fn optional_dependencies_follow_the_feature_flags() {
+    let dir = common::outside_scratch_dir("optional_dependencies_follow_the_feature_flags");
+    let ws = write_app_workspace(
+        &dir,
+        "[dependencies]\nhelper = { path = \"../../helper2\" }\n\
+         shim = { path = \"../../shim\", optional = true }\n\n\
+         [features]\ndefault = [\"legacy\"]\nlegacy = [\"dep:shim\"]\n",
+    );
+
+    // The lock file has every optional dependency
+    assert!(has_helper_duplicate(&ws, &[]));
+    assert!(has_helper_duplicate(&ws, &["--kind", "normal"]));
+    assert!(!has_helper_duplicate(&ws, &["--no-default-features"]));
+    assert!(has_helper_duplicate(
+        &ws,
+        &["--no-default-features", "--features", "legacy"]
+    ));
+    assert!(has_helper_duplicate(
+        &ws,
+        &["--no-default-features", "--all-features"]
+    ));
+}
@@ -0,0 +1,163 @@
+//! Helpers shared by the integration tests
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use cargo_depcheck::{
+    BlameMode, Config, Deps, MultiVerDepParents, MultiVerDepResults, MultiVerDeps,
+};
+use cargo_lock::Lockfile;
+use serde_json::Value;
+
+pub const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+pub const GIT: &str =
+    "git+https://github.com/someone/dup?branch=fix#0123456789abcdef0123456789abcdef01234567";
+
+/// `app -> mid -> left -> dup 1.0.0` and `mid -> right -> dup 2.0.0`, with the given versions of `mid`
+/// and source of `dup 2.0.0`
+pub fn mid_lock(mid_version: &str, dup_source: &str) -> String {
+    format!(
+        r#"version = 4
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["mid"]
+
+[[package]]
+name = "dup"
+version = "1.0.0"
+source = "{CRATES_IO}"
+
+[[package]]
+name = "dup"
+version = "2.0.0"
+source = "{dup_source}"
+
+[[package]]
+name = "left"
+version = "1.0.0"
+source = "{CRATES_IO}"
+dependencies = ["dup 1.0.0"]
+
+[[package]]
+name = "mid"
+version = "{mid_version}"
+source = "{CRATES_IO}"
+dependencies = ["left", "right"]
+
+[[package]]
+name = "right"
+version = "1.0.0"
+source = "{CRATES_IO}"
+dependencies = ["dup 2.0.0"]
+"#
+    )
+}
+
+/// Creates an empty scratch directory unique to the test
+pub fn scratch_dir(test: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(test);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Creates an empty scratch directory unique to the test run, outside of this crate so `cargo` can't
+/// find its manifest in a parent directory
+pub fn outside_scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cargo-depcheck-{}-{test}", std::process::id()));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+pub fn fixture(name: &str) -> String {
+    std::fs::read_to_string(fixture_path(name)).unwrap()
+}
+
+/// Every lock file fixture
+pub fn lock_fixtures() -> Vec<String> {
+    let mut names: Vec<_> = std::fs::read_dir(fixture_path(""))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".lock"))
+        .collect();
+    names.sort();
+    names
+}
+
+pub fn deps(lock: &str) -> Deps {
+    Deps::from_lock_file(Lockfile::from_str(lock).unwrap()).unwrap()
+}
+
+/// Finds the duplicates and blames every package (the same as `--blame all`)
+pub fn analyze(lock: &str) -> (Deps, MultiVerDepParents, MultiVerDepResults) {
+    let deps = deps(lock);
+    let multi_ver_deps = MultiVerDeps::from_deps(&deps, &Config::default()).unwrap();
+    let parents = MultiVerDepParents::build(&deps, &multi_ver_deps).unwrap();
+    let results =
+        MultiVerDepResults::build(&deps, &parents, multi_ver_deps, None, Some(BlameMode::All))
+            .unwrap();
+    (deps, parents, results)
+}
+
+pub fn json(results: &MultiVerDepResults, count: usize) -> Value {
+    let mut buffer = String::new();
+    results
+        .render_json(&mut buffer, count, Some(BlameMode::All))
+        .unwrap();
+    serde_json::from_str(&buffer).unwrap()
+}
+
+/// Removes the ANSI color codes from rendered text
+pub fn strip_styles(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// The text output of `--blame all --blame-detail`
+pub fn text(results: &MultiVerDepResults, count: usize) -> String {
+    let mut buffer = String::new();
+    results
+        .render(&mut buffer, count, Some(BlameMode::All), true)
+        .unwrap();
+    strip_styles(&buffer)
+}
+
+/// `name version` of each package in a JSON array
+pub fn packages(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|pkg| {
+            format!(
+                "{} {}",
+                pkg["name"].as_str().unwrap(),
+                pkg["version"].as_str().unwrap()
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use cargo_depcheck::{InverseTrees, MultiVerDeps};
+
+/// The cycles reported when blaming every package of the fixture, each as `name version` strings
+fn cycles(fixture: &str) -> Vec<Vec<String>> {
+    let (deps, _, results) = common::analyze(&common::fixture(fixture));
+    let json = common::json(&results, deps.count());
+
+    json["cycles"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(common::packages)
+        .collect()
+}
+
+#[test]
+fn cycles_between_members_and_registry_packages_are_reported() {
+    assert_eq!(
+        cycles("cyclic.lock"),
+        [["app 0.1.0", "helper 0.1.0"], ["bar 1.0.0", "baz 1.0.0"]]
+    );
+}
+
+#[test]
+fn two_package_cycle_is_reported() {
+    assert_eq!(
+        cycles("two_package_cycle.lock"),
+        [["bar 1.0.0", "foo 1.0.0"]]
+    );
+}
+
+#[test]
+fn package_depending_on_itself_is_reported() {
+    assert_eq!(cycles("self_cycle.lock"), [["foo 1.0.0"]]);
+}
+
+#[test]
+fn blame_finishes_on_cycles() {
+    for fixture in ["cyclic.lock", "two_package_cycle.lock", "self_cycle.lock"] {
+        let (deps, _, results) = common::analyze(&common::fixture(fixture));
+        let text = common::text(&results, deps.count());
+
+        assert!(text.contains("Dependency Cycle(s):"), "{fixture}:\n{text}");
+        assert!(text.contains("app 0.1.0 (direct: 1"), "{fixture}:\n{text}");
+    }
+}
+
+#[test]
+fn inverse_trees_finish_on_cycles() {
+    for fixture in ["cyclic.lock", "two_package_cycle.lock", "self_cycle.lock"] {
+        let deps = common::deps(&common::fixture(fixture));
+        let multi_ver_deps = MultiVerDeps::from_deps(&deps, &Default::default()).unwrap();
+        let trees = InverseTrees::build(&deps, &multi_ver_deps, None);
+
+        let mut buffer = String::new();
+        trees.render(&mut buffer).unwrap();
+        assert!(buffer.contains("foo 2.0.0"), "{fixture}:\n{buffer}");
+    }
+}
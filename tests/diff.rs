@@ -0,0 +1,53 @@
+mod common;
+
+use cargo_depcheck::DepDiff;
+use common::{CRATES_IO, GIT, mid_lock};
+use serde_json::{Value, json};
+
+fn diff(old: &str, new: &str) -> (DepDiff, Value) {
+    let (_, _, old_results) = common::analyze(old);
+    let (_, _, new_results) = common::analyze(new);
+    let diff = DepDiff::build(&old_results, &new_results);
+
+    let mut buffer = String::new();
+    diff.render_json(&mut buffer).unwrap();
+    (diff, serde_json::from_str(&buffer).unwrap())
+}
+
+#[test]
+fn identical_lock_files_have_no_changes() {
+    let lock = mid_lock("1.0.0", CRATES_IO);
+    let (diff, _) = diff(&lock, &lock);
+
+    assert!(diff.is_empty());
+}
+
+#[test]
+fn source_change_of_same_version_is_reported() {
+    let (diff, json) = diff(&mid_lock("1.0.0", CRATES_IO), &mid_lock("1.0.0", GIT));
+
+    assert!(diff.has_regressions());
+    assert_eq!(
+        json["changed"]["dup"],
+        json!({
+            "gained": [{"name": "dup", "version": "2.0.0", "source": GIT}],
+            "lost": [{"name": "dup", "version": "2.0.0", "source": CRATES_IO}],
+        })
+    );
+}
+
+#[test]
+fn blame_moving_to_another_version_is_reported() {
+    let (diff, json) = diff(&mid_lock("1.0.0", CRATES_IO), &mid_lock("2.0.0", CRATES_IO));
+    let mid = |version| json!({"name": "mid", "version": version, "source": CRATES_IO});
+
+    assert!(diff.has_regressions());
+    assert_eq!(
+        json["blame_added"],
+        json!([{"name": "dup", "package": mid("2.0.0")}])
+    );
+    assert_eq!(
+        json["blame_removed"],
+        json!([{"name": "dup", "package": mid("1.0.0")}])
+    );
+}
@@ -0,0 +1,14 @@
+use std::path::Path;
+
+use cargo_depcheck::lock_file_at_rev;
+
+#[test]
+fn option_like_revision_is_rejected() {
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join("git_option_output");
+    let _ = std::fs::remove_file(&output);
+    let rev = format!("--output={}", output.display());
+
+    let err = lock_file_at_rev(Path::new("Cargo.lock"), &rev).unwrap_err();
+    assert_eq!(err, format!("Invalid git revision '{rev}'"));
+    assert!(!output.exists());
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use cargo_depcheck::{InverseTrees, MultiVerDeps};
+use serde_json::Value;
+
+/// The rendered text and JSON of the inverse trees of `common::mid_lock`
+fn trees(max_depth: Option<usize>) -> (String, Value) {
+    let deps = common::deps(&common::mid_lock("1.0.0", common::CRATES_IO));
+    let multi_ver_deps = MultiVerDeps::from_deps(&deps, &Default::default()).unwrap();
+    let trees = InverseTrees::build(&deps, &multi_ver_deps, max_depth);
+
+    let mut text = String::new();
+    trees.render(&mut text).unwrap();
+    (text, serde_json::to_value(&trees).unwrap())
+}
+
+#[test]
+fn trees_walk_up_to_the_top_level() {
+    let (text, json) = trees(None);
+
+    assert_eq!(
+        text,
+        "dup 1.0.0\n└── left 1.0.0\n    └── mid 1.0.0\n        └── app 0.1.0\n\n\
+         dup 2.0.0\n└── right 1.0.0\n    └── mid 1.0.0\n        └── app 0.1.0\n\n"
+    );
+    assert_eq!(json[0]["truncated"], false);
+}
+
+#[test]
+fn trees_cut_off_by_the_depth_are_marked() {
+    let (text, json) = trees(Some(1));
+
+    assert_eq!(
+        text,
+        "dup 1.0.0\n└── left 1.0.0 ...\n\ndup 2.0.0\n└── right 1.0.0 ...\n\n"
+    );
+    assert_eq!(json[0]["truncated"], false);
+    assert_eq!(json[0]["dependents"][0]["truncated"], true);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use serde_json::Value;
+
+const FIXTURES: [&str; 4] = [
+    "lockfile_v1.lock",
+    "lockfile_v2.lock",
+    "lockfile_v3.lock",
+    "lockfile_v4.lock",
+];
+
+fn results(fixture: &str) -> (usize, Value) {
+    let (deps, _, results) = common::analyze(&common::fixture(fixture));
+    (deps.count(), common::json(&results, deps.count()))
+}
+
+#[test]
+fn every_version_has_the_same_duplicates_and_blame() {
+    let (count, expected) = results("lockfile_v4.lock");
+    assert_eq!(count, 4);
+
+    for fixture in FIXTURES {
+        let (fixture_count, json) = results(fixture);
+
+        assert_eq!(fixture_count, count, "{fixture}");
+        for field in [
+            "duplicates",
+            "top_level_blame",
+            "dependency_blame",
+            "summary",
+        ] {
+            assert_eq!(json[field], expected[field], "{fixture}: {field}");
+        }
+    }
+}
+
+#[test]
+fn v1_root_package_is_top_level_and_blamed() {
+    let (_, json) = results("lockfile_v1.lock");
+    let top_level_blame = json["top_level_blame"].as_array().unwrap();
+
+    assert_eq!(top_level_blame.len(), 1);
+    assert_eq!(top_level_blame[0]["package"]["name"], "app");
+    assert_eq!(top_level_blame[0]["direct"][0]["name"], "dup");
+
+    let versions = &json["duplicates"][0]["versions"];
+    assert_eq!(json["duplicates"][0]["name"], "dup");
+    assert_eq!(versions[0]["version"], "1.0.0");
+    assert_eq!(versions[1]["version"], "2.0.0");
+    assert_eq!(common::packages(&versions[0]["top_levels"]), ["app 0.1.0"]);
+    assert_eq!(common::packages(&versions[1]["top_levels"]), ["app 0.1.0"]);
+}
+
+#[test]
+fn root_package_repeated_in_the_package_list_is_added_once() {
+    // Some v1 lock files list the `[root]` package again as a `[[package]]`
+    let lock = common::fixture("lockfile_v1.lock");
+    let (root, _) = lock.split_once("\n\n").unwrap();
+    let repeated = format!("{lock}\n{}\n", root.replace("[root]", "[[package]]"));
+
+    let deps = common::deps(&repeated);
+    assert_eq!(deps.top_level_iter().count(), 1);
+    let app = deps.top_level_iter().next().unwrap();
+    assert_eq!(deps.node(app).dependencies().len(), 2);
+    for &dep in deps.node(app).dependencies() {
+        assert_eq!(deps.node(dep).dependents(), [app]);
+    }
+
+    let (_, _, repeated_results) = common::analyze(&repeated);
+    assert_eq!(
+        common::json(&repeated_results, deps.count()),
+        results("lockfile_v1.lock").1
+    );
+}
@@ -0,0 +1,29 @@
+use std::hash::{BuildHasher, RandomState};
+use std::str::FromStr;
+
+use cargo_depcheck::Package;
+use cargo_lock::{Name, SourceId, Version};
+use indexmap::IndexSet;
+
+const GIT: &str = "git+https://github.com/someone/foo?branch=fix";
+
+fn foo(source: &str) -> Package {
+    Package {
+        name: Name::from_str("foo").unwrap(),
+        version: Version::new(1, 0, 0),
+        source: Some(SourceId::from_url(source).unwrap()),
+    }
+}
+
+#[test]
+fn git_packages_with_and_without_commit_are_equal_and_hash_the_same() {
+    let locked = foo(&format!("{GIT}#0123456789abcdef0123456789abcdef01234567"));
+    let dependency = foo(GIT);
+    let hasher = RandomState::new();
+
+    assert_eq!(locked, dependency);
+    assert_eq!(hasher.hash_one(&locked), hasher.hash_one(&dependency));
+
+    let pkgs = IndexSet::from([locked]);
+    assert!(pkgs.contains(&dependency));
+}
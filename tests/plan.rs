@@ -0,0 +1,97 @@
+mod common;
+
+use serde_json::{Value, json};
+
+/// The plan steps of the lock file in the JSON output
+fn plan(lock: &str) -> Vec<Value> {
+    let (deps, parents, mut results) = common::analyze(lock);
+    results.build_plan(&deps, &parents);
+    common::json(&results, deps.count())["plan"]
+        .as_array()
+        .unwrap()
+        .clone()
+}
+
+#[test]
+fn changes_that_only_remove_a_duplicate_together_are_one_step() {
+    let bump = |name, version| json!({"action": "bump", "package": {"name": name, "version": version, "source": common::CRATES_IO}});
+
+    assert_eq!(
+        plan(&common::fixture("upgrades.lock")),
+        [
+            json!({"changes": [bump("tokio-rustls", "0.23.4")], "removes": ["rustls"], "helps": []}),
+            json!({
+                "changes": [bump("http-client", "1.0.0"), bump("legacy-b64", "0.1.0")],
+                "removes": ["base64"],
+                "helps": [],
+            }),
+        ]
+    );
+}
+
+#[test]
+fn steps_are_ordered_by_payoff() {
+    for fixture in common::lock_fixtures() {
+        let removed: Vec<_> = plan(&common::fixture(&fixture))
+            .iter()
+            .map(|step| step["removes"].as_array().unwrap().len())
+            .collect();
+
+        assert!(
+            removed.iter().all(|&count| count > 0),
+            "{fixture}: {removed:?}"
+        );
+        assert!(
+            removed.is_sorted_by(|a, b| a >= b),
+            "{fixture}: {removed:?}"
+        );
+    }
+}
+
+#[test]
+fn older_version_that_is_top_level_is_only_helped() {
+    // The local `dup` is top level, so no change can remove it
+    let lock = format!(
+        r#"version = 4
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["dup 1.0.0", "user"]
+
+[[package]]
+name = "dup"
+version = "1.0.0"
+
+[[package]]
+name = "dup"
+version = "2.0.0"
+source = "{}"
+
+[[package]]
+name = "user"
+version = "1.0.0"
+source = "{}"
+dependencies = ["dup 2.0.0"]
+"#,
+        common::CRATES_IO,
+        common::CRATES_IO
+    );
+
+    assert_eq!(
+        plan(&lock),
+        [json!({
+            "changes": [{"action": "bump", "package": {"name": "app", "version": "0.1.0"}}],
+            "removes": [],
+            "helps": ["dup"],
+        })]
+    );
+
+    let (deps, parents, mut results) = common::analyze(&lock);
+    results.build_plan(&deps, &parents);
+    let text = common::text(&results, deps.count());
+    assert!(
+        text.contains("1. bump the requirements of app 0.1.0 (helps remove: dup)\n"),
+        "{text}"
+    );
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use cargo_depcheck::{MultiVerDeps, RootCauses};
+use serde_json::{Value, json};
+
+fn root_causes(lock: &str) -> Value {
+    let (deps, _, results) = common::analyze(lock);
+    common::json(&results, deps.count())["root_causes"].clone()
+}
+
+#[test]
+fn old_hyper_induces_its_cascade() {
+    assert_eq!(
+        root_causes(&common::fixture("hyper_cascade.lock")),
+        json!([{"name": "hyper", "induced": ["h2", "http", "tokio-util"]}])
+    );
+}
+
+#[test]
+fn kept_version_does_not_depend_on_the_lock_file_order() {
+    let lock = common::fixture("hyper_cascade.lock");
+    let (head, packages) = lock.split_once("[[package]]").unwrap();
+    let mut packages: Vec<_> = packages.split("[[package]]").collect();
+    packages.reverse();
+    let reversed = format!("{head}[[package]]{}", packages.join("[[package]]"));
+
+    assert_eq!(root_causes(&reversed), root_causes(&lock));
+}
+
+#[test]
+fn root_causes_are_rendered_with_their_induced_duplicates() {
+    let deps = common::deps(&common::fixture("hyper_cascade.lock"));
+    let multi_ver_deps = MultiVerDeps::from_deps(&deps, &Default::default()).unwrap();
+    let root_causes = RootCauses::build(&deps, &multi_ver_deps);
+
+    assert_eq!((root_causes.len(), root_causes.induced_count()), (1, 3));
+
+    let mut text = String::new();
+    root_causes.render(&mut text, &multi_ver_deps).unwrap();
+    assert_eq!(
+        common::strip_styles(&text),
+        "hyper (0.14.30, 1.4.1) induces 3 duplicate(s):\n  \
+         h2 (0.3.26, 0.4.5)\n  \
+         http (0.2.12, 1.1.0)\n  \
+         tokio-util (0.7.11 (git+https://github.com/hyperium/tokio-util?branch=h2#0123456789abcdef0123456789abcdef01234567), 0.7.11)\n"
+    );
+}
+
+#[test]
+fn independent_duplicates_are_each_a_root_cause() {
+    // `dup 1.0.0` and `dup 2.0.0` come in through separate packages that aren't duplicates themselves
+    let deps = common::deps(&common::mid_lock("1.0.0", common::CRATES_IO));
+    let multi_ver_deps = MultiVerDeps::from_deps(&deps, &Default::default()).unwrap();
+    let root_causes = RootCauses::build(&deps, &multi_ver_deps);
+
+    assert_eq!((root_causes.len(), root_causes.induced_count()), (1, 0));
+
+    let mut text = String::new();
+    root_causes.render(&mut text, &multi_ver_deps).unwrap();
+    assert_eq!(text, "");
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use cargo_depcheck::{
+    BlameMode, Config, DepDiff, InverseTrees, MultiVerDepParents, MultiVerDepResults, MultiVerDeps,
+    RegistryIndex, Why,
+};
+use jsonschema::Validator;
+use serde_json::Value;
+
+fn validator() -> Validator {
+    let schema: Value = serde_json::from_str(
+        &std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/schema/output-v1.json"
+        ))
+        .unwrap(),
+    )
+    .unwrap();
+    jsonschema::validator_for(&schema).unwrap()
+}
+
+fn assert_valid(validator: &Validator, json: &str, context: &str) {
+    let json: Value = serde_json::from_str(json).unwrap();
+    let errors: Vec<_> = validator
+        .iter_errors(&json)
+        .map(|error| format!("{} at {}", error, error.instance_path()))
+        .collect();
+    assert!(errors.is_empty(), "{context}: {errors:#?}");
+}
+
+/// The output with every section filled in: trees, blame, upgrades, plan and baseline
+fn full_output(lock: &str, blame_mode: Option<BlameMode>) -> String {
+    let deps = common::deps(lock);
+    let multi_ver_deps = MultiVerDeps::from_deps(&deps, &Config::default()).unwrap();
+    let parents = MultiVerDepParents::build(&deps, &multi_ver_deps).unwrap();
+    let trees = InverseTrees::build(&deps, &multi_ver_deps, Some(2));
+    let mut results =
+        MultiVerDepResults::build(&deps, &parents, multi_ver_deps, Some(trees), blame_mode)
+            .unwrap();
+
+    results.suggest_upgrades(&mut RegistryIndex::new(Some(&common::fixture_path(
+        "index",
+    ))));
+    results.build_plan(&deps, &parents);
+    let (_, _, baseline) = common::analyze(&common::mid_lock("1.0.0", common::CRATES_IO));
+    results.apply_baseline(&baseline.baseline());
+
+    let mut buffer = String::new();
+    results
+        .render_json(&mut buffer, deps.count(), blame_mode)
+        .unwrap();
+    buffer
+}
+
+#[test]
+fn check_output_matches_the_schema() {
+    let validator = validator();
+
+    for fixture in common::lock_fixtures() {
+        let lock = common::fixture(&fixture);
+        for blame_mode in [None, Some(BlameMode::TopLevel), Some(BlameMode::All)] {
+            assert_valid(&validator, &full_output(&lock, blame_mode), &fixture);
+        }
+
+        // Without any of the optional sections
+        let (deps, _, results) = common::analyze(&lock);
+        let mut buffer = String::new();
+        results
+            .render_json(&mut buffer, deps.count(), None)
+            .unwrap();
+        assert_valid(&validator, &buffer, &fixture);
+    }
+}
+
+#[test]
+fn diff_output_matches_the_schema() {
+    let validator = validator();
+    let locks = [
+        common::mid_lock("1.0.0", common::CRATES_IO),
+        common::mid_lock("2.0.0", common::GIT),
+        common::fixture("why.lock"),
+    ];
+
+    for old in &locks {
+        for new in &locks {
+            let (_, _, old_results) = common::analyze(old);
+            let (_, _, new_results) = common::analyze(new);
+            let mut buffer = String::new();
+            DepDiff::build(&old_results, &new_results)
+                .render_json(&mut buffer)
+                .unwrap();
+            assert_valid(&validator, &buffer, &buffer);
+        }
+    }
+}
+
+#[test]
+fn why_output_matches_the_schema() {
+    let validator = validator();
+
+    for (fixture, spec) in [
+        ("why.lock", "syn"),
+        ("why.lock", "syn@1"),
+        ("why.lock", "helper"),
+        ("indirect_chain.lock", "foo"),
+        ("git_source.lock", "foo"),
+    ] {
+        let (deps, _, results) = common::analyze(&common::fixture(fixture));
+        let mut buffer = String::new();
+        Why::build(&deps, &results, spec, 1)
+            .unwrap()
+            .render_json(&mut buffer)
+            .unwrap();
+        assert_valid(&validator, &buffer, &format!("{fixture} {spec}"));
+    }
+}
+
+#[test]
+fn outputs_only_match_their_own_part_of_the_schema() {
+    let validator = validator();
+    let (deps, _, results) = common::analyze(&common::fixture("why.lock"));
+
+    let mut buffer = String::new();
+    results
+        .render_json(&mut buffer, deps.count(), None)
+        .unwrap();
+    let mut json: Value = serde_json::from_str(&buffer).unwrap();
+    json["format_version"] = 2.into();
+    assert!(!validator.is_valid(&json));
+
+    json["format_version"] = 1.into();
+    json.as_object_mut().unwrap().remove("duplicates");
+    assert!(!validator.is_valid(&json));
+}
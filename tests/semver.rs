@@ -0,0 +1,116 @@
+mod common;
+
+use cargo_depcheck::is_semver_compatible;
+use semver::Version;
+use serde_json::{Value, json};
+
+fn compatible(a: &str, b: &str) -> bool {
+    let (a, b) = (Version::parse(a).unwrap(), Version::parse(b).unwrap());
+    assert_eq!(is_semver_compatible(&a, &b), is_semver_compatible(&b, &a));
+    is_semver_compatible(&a, &b)
+}
+
+#[test]
+fn caret_compatibility_follows_the_first_non_zero_component() {
+    assert!(!compatible("1.0.0", "2.0.0"));
+    assert!(compatible("1.2.0", "1.4.1"));
+    assert!(!compatible("0.3.0", "0.4.0"));
+    assert!(compatible("0.3.1", "0.3.9"));
+    assert!(!compatible("0.0.1", "0.0.2"));
+    assert!(compatible("0.0.1", "0.0.1"));
+    assert!(!compatible("0.1.0", "1.1.0"));
+}
+
+/// `app` depending on every given version of `dup`
+fn dup_lock(versions: &[&str]) -> String {
+    let mut lock =
+        String::from("version = 4\n\n[[package]]\nname = \"app\"\nversion = \"0.1.0\"\n");
+    let dependencies: Vec<_> = versions
+        .iter()
+        .map(|version| format!("\"dup {version}\""))
+        .collect();
+    lock.push_str(&format!("dependencies = [{}]\n", dependencies.join(", ")));
+
+    for version in versions {
+        lock.push_str(&format!(
+            "\n[[package]]\nname = \"dup\"\nversion = \"{version}\"\nsource = \"{}\"\n",
+            common::CRATES_IO
+        ));
+    }
+    lock
+}
+
+/// The `semver` and `version_pairs` of the duplicate in the JSON output
+fn classify(versions: &[&str]) -> (Value, Value) {
+    let (deps, _, results) = common::analyze(&dup_lock(versions));
+    let json = common::json(&results, deps.count());
+    let dup = &json["duplicates"][0];
+    (dup["semver"].clone(), dup["version_pairs"].clone())
+}
+
+fn pair(a: &str, b: &str, compatible: bool) -> Value {
+    json!({"versions": [a, b], "compatible": compatible})
+}
+
+#[test]
+fn major_versions_are_incompatible() {
+    assert_eq!(
+        classify(&["1.0.0", "2.0.0"]),
+        (
+            json!("incompatible"),
+            json!([pair("1.0.0", "2.0.0", false)])
+        )
+    );
+}
+
+#[test]
+fn zero_minor_versions_are_incompatible() {
+    assert_eq!(
+        classify(&["0.3.0", "0.4.0"]),
+        (
+            json!("incompatible"),
+            json!([pair("0.3.0", "0.4.0", false)])
+        )
+    );
+    assert_eq!(
+        classify(&["0.0.1", "0.0.2"]),
+        (
+            json!("incompatible"),
+            json!([pair("0.0.1", "0.0.2", false)])
+        )
+    );
+}
+
+#[test]
+fn same_major_versions_are_compatible() {
+    assert_eq!(
+        classify(&["1.2.0", "1.4.1"]),
+        (json!("compatible"), json!([pair("1.2.0", "1.4.1", true)]))
+    );
+}
+
+#[test]
+fn some_compatible_pairs_are_mixed() {
+    assert_eq!(
+        classify(&["1.2.0", "1.4.1", "2.0.0"]),
+        (
+            json!("mixed"),
+            json!([
+                pair("1.2.0", "1.4.1", true),
+                pair("1.2.0", "2.0.0", false),
+                pair("1.4.1", "2.0.0", false),
+            ])
+        )
+    );
+}
+
+#[test]
+fn source_only_duplicate_has_no_pairs() {
+    let (deps, _, results) = common::analyze(&common::fixture("git_source.lock"));
+    let json = common::json(&results, deps.count());
+
+    for dup in json["duplicates"].as_array().unwrap() {
+        assert_eq!(dup["semver"], Value::Null, "{dup}");
+        assert_eq!(dup["version_pairs"], json!([]), "{dup}");
+    }
+}
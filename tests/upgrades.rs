@@ -0,0 +1,37 @@
+mod common;
+
+use cargo_depcheck::RegistryIndex;
+use serde_json::json;
+
+#[test]
+fn upgrades_are_suggested_from_the_index() {
+    let (deps, _, mut results) = common::analyze(&common::fixture("upgrades.lock"));
+    results.suggest_upgrades(&mut RegistryIndex::new(Some(&common::fixture_path(
+        "index",
+    ))));
+    let json = common::json(&results, deps.count());
+
+    let package =
+        |name, version| json!({"name": name, "version": version, "source": common::CRATES_IO});
+    assert_eq!(
+        json["upgrades"],
+        json!([
+            {
+                "name": "base64",
+                "version": "0.13.1",
+                "source": common::CRATES_IO,
+                // 2.0.0-beta.1 is a pre-release and base64 is only a dev dependency of 1.1.0
+                "upgrades": [{"package": package("http-client", "1.0.0"), "to": "1.1.0", "uses": null}],
+                "remaining": [package("legacy-b64", "0.1.0")],
+            },
+            {
+                "name": "rustls",
+                "version": "0.20.9",
+                "source": common::CRATES_IO,
+                // 0.24.2 is yanked and 0.25.0 needs a rustls version not in the lock file
+                "upgrades": [{"package": package("tokio-rustls", "0.23.4"), "to": "0.24.1", "uses": "0.21.0"}],
+                "remaining": [],
+            },
+        ])
+    );
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use cargo_depcheck::Why;
+use serde_json::Value;
+
+fn why(lock: &str, spec: &str) -> (String, Value) {
+    let (deps, _, results) = common::analyze(lock);
+    let why = Why::build(&deps, &results, spec, 10).unwrap();
+
+    let mut text = String::new();
+    why.render(&mut text).unwrap();
+    let mut json = String::new();
+    why.render_json(&mut json).unwrap();
+    (
+        common::strip_styles(&text),
+        serde_json::from_str(&json).unwrap(),
+    )
+}
+
+/// Each directly blamed package and the versions it is blamed for, as `name version` strings
+fn direct_blame(json: &Value) -> Vec<(String, Vec<String>)> {
+    json["direct_blame"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|blame| {
+            let package = &blame["package"];
+            let versions = blame["versions"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|version| version["version"].as_str().unwrap().to_string())
+                .collect();
+            (
+                format!(
+                    "{} {}",
+                    package["name"].as_str().unwrap(),
+                    package["version"].as_str().unwrap()
+                ),
+                versions,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn blame_is_limited_to_the_matched_versions() {
+    let lock = common::fixture("why.lock");
+
+    let (_, json) = why(&lock, "syn");
+    assert_eq!(
+        direct_blame(&json),
+        [
+            (
+                "app 0.1.0".to_string(),
+                vec![
+                    "1.0.109".to_string(),
+                    "2.0.50".to_string(),
+                    "3.0.8".to_string()
+                ]
+            ),
+            (
+                "helper 1.0.0".to_string(),
+                vec!["1.0.109".to_string(), "3.0.8".to_string()]
+            ),
+        ]
+    );
+
+    let (text, json) = why(&lock, "syn@2");
+    assert_eq!(
+        direct_blame(&json),
+        [("app 0.1.0".to_string(), vec!["2.0.50".to_string()])]
+    );
+    assert!(!text.contains("3.0.8"), "{text}");
+}
+
+#[test]
+fn unblamed_duplicate_is_not_a_single_version() {
+    let lock = common::fixture("why.lock").replace(" \"helper\",\n", "");
+
+    let (text, _) = why(&lock, "syn");
+    assert!(!text.contains("single version"), "{text}");
+    assert!(
+        text.contains("Nothing in the analyzed packages is to blame"),
+        "{text}"
+    );
+
+    let (text, _) = why(&lock, "derive-a");
+    assert!(text.contains("derive-a has a single version"), "{text}");
+}
@@ -0,0 +1,197 @@
+mod common;
+
+use std::path::Path;
+
+use cargo_depcheck::{PackageKind, Workspace};
+
+/// Writes the manifest (and an empty `src/lib.rs`) of the package in the directory
+fn write_manifest(dir: &Path, contents: &str) {
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src/lib.rs"), "").unwrap();
+    std::fs::write(dir.join("Cargo.toml"), contents).unwrap();
+}
+
+fn write_package(dir: &Path, name: &str) {
+    write_manifest(
+        dir,
+        &format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n"),
+    );
+}
+
+/// `(members, selected)` of the workspace, each sorted by name
+fn members(workspace: &Workspace, names: &[&str]) -> (Vec<String>, Vec<String>) {
+    let local = |name: &str| cargo_depcheck::Package {
+        name: name.parse().unwrap(),
+        version: "0.1.0".parse().unwrap(),
+        source: None,
+    };
+    let mut members: Vec<_> = names
+        .iter()
+        .filter(|name| workspace.is_member(&local(name)))
+        .map(ToString::to_string)
+        .collect();
+    let mut selected: Vec<_> = names
+        .iter()
+        .filter(|name| workspace.is_selected(&local(name)))
+        .map(ToString::to_string)
+        .collect();
+    members.sort();
+    selected.sort();
+    (members, selected)
+}
+
+const NAMES: [&str; 6] = ["root", "a", "b", "c", "old", "tool"];
+
+#[test]
+fn missing_manifest_is_no_workspace() {
+    let dir = common::scratch_dir("missing_manifest_is_no_workspace");
+    assert!(Workspace::from_manifest(&dir).unwrap().is_none());
+}
+
+#[test]
+fn single_package_is_its_own_workspace() {
+    let dir = common::scratch_dir("single_package_is_its_own_workspace");
+    write_package(&dir, "root");
+
+    let workspace = Workspace::from_manifest(&dir).unwrap().unwrap();
+    assert_eq!(
+        members(&workspace, &NAMES),
+        (vec!["root".into()], vec!["root".into()])
+    );
+    assert_eq!(workspace.manifest_paths(), [dir.join("Cargo.toml")]);
+}
+
+#[test]
+fn member_globs_skip_excluded_and_non_package_directories() {
+    let dir = common::scratch_dir("member_globs_skip_excluded_and_non_package_directories");
+    write_manifest(
+        &dir,
+        "[workspace]\nmembers = [\"crates/*\", \"tools/t??l\"]\nexclude = [\"crates/old\"]\n",
+    );
+    write_package(&dir.join("crates/a"), "a");
+    write_package(&dir.join("crates/b"), "b");
+    write_package(&dir.join("crates/old"), "old");
+    std::fs::create_dir_all(dir.join("crates/docs")).unwrap();
+    std::fs::write(dir.join("crates/README.md"), "").unwrap();
+    write_package(&dir.join("tools/tool"), "tool");
+    write_package(&dir.join("tools/tools"), "c");
+
+    let workspace = Workspace::from_manifest(&dir).unwrap().unwrap();
+    let all: Vec<String> = vec!["a".into(), "b".into(), "tool".into()];
+    assert_eq!(members(&workspace, &NAMES), (all.clone(), all));
+}
+
+#[test]
+fn default_members_are_selected() {
+    let dir = common::scratch_dir("default_members_are_selected");
+    write_manifest(
+        &dir,
+        "[package]\nname = \"root\"\nversion = \"0.1.0\"\n\n\
+         [workspace]\nmembers = [\"crates/*\"]\ndefault-members = [\"crates/b\"]\n",
+    );
+    write_package(&dir.join("crates/a"), "a");
+    write_package(&dir.join("crates/b"), "b");
+
+    // The root package is always a member
+    let workspace = Workspace::from_manifest(&dir).unwrap().unwrap();
+    assert_eq!(
+        members(&workspace, &NAMES),
+        (
+            vec!["a".into(), "b".into(), "root".into()],
+            vec!["b".into()]
+        )
+    );
+}
+
+#[test]
+fn registry_package_with_a_member_name_is_not_a_member() {
+    let dir = common::scratch_dir("registry_package_with_a_member_name_is_not_a_member");
+    write_package(&dir, "root");
+
+    let workspace = Workspace::from_manifest(&dir).unwrap().unwrap();
+    let registry = cargo_depcheck::Package {
+        name: "root".parse().unwrap(),
+        version: "0.1.0".parse().unwrap(),
+        source: Some(common::CRATES_IO.parse().unwrap()),
+    };
+    assert!(!workspace.is_member(&registry));
+    assert!(!workspace.is_selected(&registry));
+}
+
+/// `app` (a member) depends on `vendored` (a local package outside the workspace), and `tool` (a
+/// member that isn't selected) on `dup 2.0.0`
+fn path_dependency_lock() -> String {
+    format!(
+        r#"version = 4
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["dup 1.0.0", "vendored"]
+
+[[package]]
+name = "dup"
+version = "1.0.0"
+source = "{crates_io}"
+
+[[package]]
+name = "dup"
+version = "2.0.0"
+source = "{crates_io}"
+
+[[package]]
+name = "tool"
+version = "0.1.0"
+dependencies = ["dup 2.0.0"]
+
+[[package]]
+name = "vendored"
+version = "0.1.0"
+"#,
+        crates_io = common::CRATES_IO
+    )
+}
+
+#[test]
+fn local_packages_outside_the_workspace_are_path_dependencies() {
+    let dir = common::scratch_dir("local_packages_outside_the_workspace_are_path_dependencies");
+    write_manifest(
+        &dir,
+        "[workspace]\nmembers = [\"app\", \"tool\"]\ndefault-members = [\"app\"]\n",
+    );
+    write_package(&dir.join("app"), "app");
+    write_package(&dir.join("tool"), "tool");
+    let workspace = Workspace::from_manifest(&dir).unwrap().unwrap();
+
+    let deps = common::deps(&path_dependency_lock())
+        .with_workspace(&workspace)
+        .unwrap();
+    let kind = |name: &str| {
+        let (_, dep) = deps.iter().find(|(dep, _)| dep.as_str() == name).unwrap();
+        let kinds: Vec<_> = dep.iter().map(|id| deps.node(id).kind()).collect();
+        kinds
+    };
+
+    assert_eq!(kind("app"), [PackageKind::WorkspaceMember]);
+    assert_eq!(kind("vendored"), [PackageKind::LocalPath]);
+    // `tool` isn't selected, so neither it nor `dup 2.0.0` is analyzed
+    assert!(deps.iter().all(|(name, _)| name.as_str() != "tool"));
+    assert_eq!(kind("dup"), [PackageKind::Remote]);
+}
+
+#[test]
+fn selected_members_missing_from_the_lock_file_are_an_error() {
+    let dir = common::scratch_dir("selected_members_missing_from_the_lock_file_are_an_error");
+    write_manifest(&dir, "[workspace]\nmembers = [\"other\"]\n");
+    write_package(&dir.join("other"), "other");
+    let workspace = Workspace::from_manifest(&dir).unwrap().unwrap();
+
+    let error = common::deps(&path_dependency_lock())
+        .with_workspace(&workspace)
+        .err()
+        .unwrap();
+    assert_eq!(
+        error,
+        "None of the selected workspace members (other) are in the lock file"
+    );
+}